@@ -0,0 +1,159 @@
+use near_sdk::{json_types::U128, PromiseOrValue, PromiseResult};
+
+use crate::consts::{GAS_FOR_RESOLVE_TRANSFER, NO_DEPOSIT};
+
+use crate::*;
+
+/// Bounty funded by a registry owner for contributing data to the registry.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bounty {
+    /// Registry the data is contributed to.
+    pub unique_identifier: AccountId,
+    /// Account that funded the bounty.
+    pub owner: AccountId,
+    /// Description of the data task.
+    pub description: String,
+    /// Token the bounty is paid in, `None` for $NEAR.
+    pub token: Option<AccountId>,
+    /// Amount paid out per claim.
+    pub amount: U128,
+    /// How many times the bounty can still be claimed.
+    pub times: u32,
+}
+
+/// Bounty as given by its funder.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct BountyInput {
+    pub unique_identifier: AccountId,
+    pub description: String,
+    pub amount: U128,
+    pub times: u32,
+}
+
+impl Contract {
+    /// Adds bounty funded by the registry owner with `deposit` of given token.
+    pub(crate) fn internal_add_bounty(
+        &mut self,
+        owner: &AccountId,
+        token: Option<AccountId>,
+        deposit: Balance,
+        bounty: BountyInput,
+    ) -> u64 {
+        let registry = self
+            .internal_find_registry(&bounty.unique_identifier)
+            .expect("ERR_NO_REGISTRY");
+        assert_eq!(registry.owner, *owner, "ERR_NOT_ALLOWED");
+        assert!(
+            bounty.amount.0 > 0 && bounty.times > 0,
+            "ERR_INVALID_BOUNTY"
+        );
+        assert_eq!(
+            bounty.amount.0 * bounty.times as Balance,
+            deposit,
+            "ERR_WRONG_DEPOSIT"
+        );
+        if token.is_none() {
+            self.reserved_amount += deposit;
+        }
+        let id = self.last_bounty_id;
+        self.bounties.insert(
+            &id,
+            &Bounty {
+                unique_identifier: bounty.unique_identifier,
+                owner: owner.clone(),
+                description: bounty.description,
+                token,
+                amount: bounty.amount,
+                times: bounty.times,
+            },
+        );
+        self.last_bounty_id += 1;
+        id
+    }
+
+    /// Takes one claim of the bounty, once a `BountyDone` proposal was approved.
+    pub(crate) fn internal_claim_bounty(&mut self, bounty_id: u64) -> Result<(), &'static str> {
+        let mut bounty = self.bounties.get(&bounty_id).ok_or("ERR_NO_BOUNTY")?;
+        bounty.times = bounty.times.checked_sub(1).ok_or("ERR_BOUNTY_CLAIMED")?;
+        self.bounties.insert(&bounty_id, &bounty);
+        Ok(())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds bounty for contributing data to given registry, paid in $NEAR.
+    /// Attached deposit must be `amount` times `times`. Only for the registry owner.
+    /// NEP-141 bounties are added with `ft_transfer_call` of the token, with the bounty as `msg`.
+    #[payable]
+    pub fn add_bounty(&mut self, bounty: BountyInput) -> u64 {
+        self.internal_add_bounty(
+            &env::predecessor_account_id(),
+            None,
+            env::attached_deposit(),
+            bounty,
+        )
+    }
+
+    /// Removes bounty, refunding its unclaimed amount. Only for the bounty owner.
+    /// Claims already approved are still paid out.
+    pub fn remove_bounty(&mut self, id: u64) -> PromiseOrValue<()> {
+        let mut bounty = self.bounties.get(&id).expect("ERR_NO_BOUNTY");
+        assert_eq!(
+            bounty.owner,
+            env::predecessor_account_id(),
+            "ERR_NOT_ALLOWED"
+        );
+        let times = bounty.times;
+        let refund = bounty.amount.0 * times as Balance;
+        bounty.times = 0;
+        self.bounties.insert(&id, &bounty);
+        if refund == 0 {
+            return PromiseOrValue::Value(());
+        }
+        if bounty.token.is_none() {
+            self.reserved_amount -= refund;
+        }
+        match self.internal_payout(
+            &bounty.token,
+            &bounty.owner,
+            refund,
+            format!("Removed bounty {}", id),
+            &None,
+        ) {
+            PromiseOrValue::Promise(promise) => promise
+                .then(ext_self::on_remove_bounty_callback(
+                    id,
+                    times,
+                    env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_RESOLVE_TRANSFER,
+                ))
+                .into(),
+            value => value,
+        }
+    }
+
+    /// Restores unclaimed bounty if its refund failed.
+    #[private]
+    pub fn on_remove_bounty_callback(&mut self, bounty_id: u64, times: u32) -> bool {
+        assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => true,
+            PromiseResult::Failed => {
+                let mut bounty = self.bounties.get(&bounty_id).expect("ERR_NO_BOUNTY");
+                bounty.times += times;
+                if bounty.token.is_none() {
+                    self.reserved_amount += bounty.amount.0 * times as Balance;
+                }
+                self.bounties.insert(&bounty_id, &bounty);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use near_sdk::json_types::Base64VecU8;
+
+use crate::*;
+
+/// Largest integer up to which every integer is exactly representable as `f64`.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// Same JSON value with object keys sorted and integral floats written as integers,
+/// so equal values serialize the same.
+pub(crate) fn canonicalize(value: &Value) -> Value {
+    match value {
+        Value::Number(number) => match number.as_f64() {
+            Some(float)
+                if number.is_f64() && float.fract() == 0.0 && float.abs() <= MAX_SAFE_INTEGER =>
+            {
+                Value::from(float as i64)
+            }
+            _ => value.clone(),
+        },
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), canonicalize(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonicalize).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Canonical JSON encoding of given value, as rows and columns are stored.
+pub(crate) fn canonical_json(value: &Value) -> Vec<u8> {
+    canonicalize(value).to_string().into_bytes()
+}
+
+/// sha256 of the canonical JSON of given row data, or of the bytes as is if they aren't JSON.
+pub(crate) fn content_hash(data: &[u8]) -> Base64VecU8 {
+    let hash = match serde_json::from_slice::<Value>(data) {
+        Ok(value) => env::sha256(canonicalize(&value).to_string().as_bytes()),
+        Err(_) => env::sha256(data),
+    };
+    Base64VecU8(hash)
+}
@@ -0,0 +1,232 @@
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+
+use crate::consts::{
+    GAS_FOR_TOKEN_GATE_CALLBACK, LOCAL_VOTES_ROLE, MAX_VOTE_COMMITS, NO_DEPOSIT,
+    VOTE_COMMIT_DEPOSIT,
+};
+use crate::types::{Action, LocalVote, Vote};
+use crate::*;
+
+/// Rules for voting on proposals to a registry by committing to a vote and revealing it later.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CommitRevealPolicy {
+    /// How long after submission votes can be committed, in nanoseconds.
+    pub commit_period: U64,
+    /// How long after the commit period votes can be revealed, in nanoseconds.
+    pub reveal_period: U64,
+    /// Weight of revealed votes required to decide the proposal. Otherwise it expires.
+    pub quorum: U128,
+}
+
+/// Hidden vote of an account until it is revealed.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct VoteCommit {
+    /// sha256 of `<action>:<salt>`, e.g. `VoteApprove:some salt`.
+    pub hash: Vec<u8>,
+    /// Revealed vote and when it was revealed.
+    pub revealed: Option<(Action, u64)>,
+    /// Deposit paid for the commit, until it is refunded on reveal.
+    pub deposit: Balance,
+}
+
+impl Contract {
+    /// Proposal in progress with its commit-reveal policy, and ends of its commit and reveal periods.
+    fn internal_get_commit_reveal(&self, id: u64) -> (Proposal, CommitRevealPolicy, u64, u64) {
+        let proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::InProgress,
+            "ERR_PROPOSAL_NOT_READY_FOR_VOTE"
+        );
+        let policy = proposal
+            .commit_reveal_policy
+            .clone()
+            .expect("ERR_NOT_COMMIT_REVEAL");
+        let commit_end = proposal.submission_time.0 + policy.commit_period.0;
+        let reveal_end = commit_end + policy.reveal_period.0;
+        (proposal, policy, commit_end, reveal_end)
+    }
+
+    /// Checks that given account can commit a vote on the proposal with given deposit.
+    /// A first commit takes `VOTE_COMMIT_DEPOSIT`, replacing it takes none.
+    pub(crate) fn internal_check_commit(
+        &self,
+        id: u64,
+        voter: &AccountId,
+        hash: &Base64VecU8,
+        deposit: Balance,
+    ) -> Result<(), &'static str> {
+        let proposal: Proposal = self.proposals.get(&id).ok_or("ERR_NO_PROPOSAL")?.into();
+        if proposal.status != ProposalStatus::InProgress {
+            return Err("ERR_PROPOSAL_NOT_READY_FOR_VOTE");
+        }
+        let policy = proposal
+            .commit_reveal_policy
+            .ok_or("ERR_NOT_COMMIT_REVEAL")?;
+        if env::block_timestamp() >= proposal.submission_time.0 + policy.commit_period.0 {
+            return Err("ERR_COMMIT_PERIOD_OVER");
+        }
+        if hash.0.len() != 32 {
+            return Err("ERR_INVALID_HASH");
+        }
+        let commits = self.vote_commits.get(&id).unwrap_or_default();
+        if commits.contains_key(voter) {
+            if deposit != 0 {
+                return Err("ERR_WRONG_DEPOSIT");
+            }
+        } else if deposit != VOTE_COMMIT_DEPOSIT {
+            return Err("ERR_WRONG_DEPOSIT");
+        } else if commits.len() >= MAX_VOTE_COMMITS {
+            return Err("ERR_TOO_MANY_COMMITS");
+        }
+        Ok(())
+    }
+
+    /// Commits vote of given account on the proposal, once checked by `internal_check_commit`.
+    pub(crate) fn internal_commit_vote(
+        &mut self,
+        id: u64,
+        voter: AccountId,
+        hash: Base64VecU8,
+        deposit: Balance,
+    ) {
+        let mut commits = self.vote_commits.get(&id).unwrap_or_default();
+        let deposit = commits
+            .get(&voter)
+            .map(|commit| commit.deposit)
+            .unwrap_or(deposit);
+        commits.insert(
+            voter,
+            VoteCommit {
+                hash: hash.into(),
+                revealed: None,
+                deposit,
+            },
+        );
+        self.vote_commits.insert(&id, &commits);
+    }
+
+    /// Drops vote commits of given proposal, refunding deposits of unrevealed ones.
+    /// Used when the proposal leaves `InProgress` without being tallied.
+    pub(crate) fn internal_refund_vote_commits(&mut self, id: u64) {
+        for (voter, commit) in self.vote_commits.remove(&id).unwrap_or_default() {
+            if commit.deposit > 0 {
+                Promise::new(voter).transfer(commit.deposit);
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets commit-reveal voting for proposals to given registry. `None` votes in public
+    /// with `act_proposal`. Proposals keep the policy they were submitted with.
+    /// Only for the registry owner.
+    pub fn set_registry_commit_reveal_policy(
+        &mut self,
+        unique_identifier: AccountId,
+        commit_reveal_policy: Option<CommitRevealPolicy>,
+    ) {
+        let registry = self.internal_owned_registry_mut(&unique_identifier);
+        registry.commit_reveal_policy = commit_reveal_policy;
+    }
+
+    /// Commits to a vote on given proposal during its commit period.
+    /// `hash` is sha256 of `<action>:<salt>`. Committing again replaces the previous commit.
+    /// A first commit requires `VOTE_COMMIT_DEPOSIT` attached, refunded when the vote is revealed.
+    /// For token-gated registries the vote is committed once the caller's holdings are checked.
+    #[payable]
+    pub fn commit_vote(&mut self, id: u64, hash: Base64VecU8) {
+        let voter = env::predecessor_account_id();
+        let deposit = env::attached_deposit();
+        if let Err(err) = self.internal_check_commit(id, &voter, &hash, deposit) {
+            env::panic_str(err);
+        }
+        let proposal: Proposal = self.proposals.get(&id).unwrap().into();
+        match self.internal_token_gate(&proposal.unique_identifier) {
+            Some(gate) => {
+                let _ = self.internal_query_token_gate(&gate, &voter).then(
+                    ext_self::on_token_gate_commit_callback(
+                        id,
+                        voter,
+                        hash,
+                        U128(deposit),
+                        env::current_account_id(),
+                        NO_DEPOSIT,
+                        GAS_FOR_TOKEN_GATE_CALLBACK,
+                    ),
+                );
+            }
+            None => self.internal_commit_vote(id, voter, hash, deposit),
+        }
+    }
+
+    /// Reveals committed vote on given proposal during its reveal period.
+    pub fn reveal_vote(&mut self, id: u64, action: Action, salt: String) {
+        let (_, _, commit_end, reveal_end) = self.internal_get_commit_reveal(id);
+        let now = env::block_timestamp();
+        assert!(
+            now >= commit_end && now < reveal_end,
+            "ERR_NOT_REVEAL_PERIOD"
+        );
+        assert!(
+            matches!(action, Action::VoteApprove | Action::VoteReject),
+            "ERR_WRONG_ACTION"
+        );
+        let mut commits = self.vote_commits.get(&id).unwrap_or_default();
+        let commit = commits
+            .get_mut(&env::predecessor_account_id())
+            .expect("ERR_NO_COMMIT");
+        let hash = env::sha256(format!("{}:{}", action.to_policy_label(), salt).as_bytes());
+        assert_eq!(commit.hash, hash, "ERR_HASH_MISMATCH");
+        commit.revealed = Some((action, now));
+        let deposit = std::mem::take(&mut commit.deposit);
+        self.vote_commits.insert(&id, &commits);
+        if deposit > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(deposit);
+        }
+    }
+
+    /// Counts revealed votes once the reveal period is over and decides the proposal.
+    /// Unrevealed votes are discarded and their deposits forfeited. Can be called by anyone.
+    pub fn tally_votes(&mut self, id: u64) -> ProposalStatus {
+        let (mut proposal, policy, _, reveal_end) = self.internal_get_commit_reveal(id);
+        assert!(
+            env::block_timestamp() >= reveal_end,
+            "ERR_REVEAL_PERIOD_NOT_OVER"
+        );
+        let mut votes = vec![];
+        for (voter, commit) in self.vote_commits.remove(&id).unwrap_or_default() {
+            if let Some((action, revealed_at)) = commit.revealed {
+                proposal.votes.insert(voter.clone(), action.clone().into());
+                votes.push(LocalVote {
+                    stake: self.internal_voting_power_at(&voter, proposal.submission_time.0),
+                    account_id: voter,
+                    action,
+                    voted_at: revealed_at,
+                });
+            }
+        }
+        votes.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+        self.voting_power.insert(id, votes);
+        self.internal_count_local_votes(id, &mut proposal);
+
+        let counts = proposal.vote_counts[LOCAL_VOTES_ROLE];
+        let approve = counts[Vote::Approve as usize];
+        let reject = counts[Vote::Reject as usize];
+        if approve + reject < policy.quorum.0 {
+            self.internal_expire_proposal(id, &mut proposal);
+        } else if approve > reject {
+            self.internal_approve_proposal(id, &mut proposal);
+        } else {
+            self.internal_record_rejected(&proposal);
+            self.internal_close_dispute(&proposal, false);
+            proposal.status = ProposalStatus::Rejected;
+            self.internal_release_bond(&mut proposal, true);
+        }
+        self.proposals
+            .insert(&id, &VersionedProposal::V2(proposal.clone()));
+        proposal.status
+    }
+}
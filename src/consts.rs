@@ -15,5 +15,51 @@ pub const ONE_YOCTO_NEAR: Balance = 1;
 /// Gas for single ft_transfer call.
 pub const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
 
-// pub const VOTING_COUNT: u64 = 24 * 60 * 60;
 pub const ONE_NEAR: Balance = 100_000_000_000_000_000_000_000;
+
+/// Role under which weights of votes relayed by this contract are counted in `vote_counts`.
+pub const LOCAL_VOTES_ROLE: &str = "delegated";
+
+/// Default period after submission in which a withdrawn proposal gets its bond back in full.
+pub const DEFAULT_WITHDRAW_GRACE_PERIOD: u64 = 60 * 60 * 1_000_000_000;
+
+/// Default share of the bond, in basis points, refunded for a withdrawn proposal after the grace period.
+pub const DEFAULT_WITHDRAW_REFUND_BPS: u32 = 5_000;
+
+/// Gas for the balance or token query of a token-gated registry.
+pub const GAS_FOR_TOKEN_GATE_QUERY: Gas = Gas(5_000_000_000_000);
+
+/// Gas for the callback that goes on with a proposal or vote once the token gate passed.
+pub const GAS_FOR_TOKEN_GATE_CALLBACK: Gas = Gas(30_000_000_000_000);
+
+/// Gas for minting or burning the NFT receipt of a registry row.
+pub const GAS_FOR_RECEIPT: Gas = Gas(15_000_000_000_000);
+
+/// Gas kept by `process_receipts` for its own bookkeeping after the last receipt.
+pub const GAS_FOR_RECEIPT_QUEUE: Gas = Gas(5_000_000_000_000);
+
+/// Most gas a subscriber can ask for its `on_registry_change` notification.
+pub const MAX_SUBSCRIBER_GAS: Gas = Gas(20_000_000_000_000);
+
+/// Deposit for a committed vote, covering its storage. Refunded when the vote is revealed.
+pub const VOTE_COMMIT_DEPOSIT: Balance = ONE_NEAR / 100;
+
+/// Most votes that can be committed on a proposal.
+pub const MAX_VOTE_COMMITS: usize = 100;
+
+/// Most rows re-encoded by the call that changes the schema of a registry.
+pub const MAX_REENCODED_ROWS: usize = 50;
+
+/// Most subscribers a registry can have.
+pub const MAX_SUBSCRIBERS: usize = 10;
+
+/// Deposit for subscribing to a registry, covering its storage. Refunded on unsubscribe.
+pub const SUBSCRIPTION_DEPOSIT: Balance = ONE_NEAR / 10;
+
+/// Gas for the callback resolving a proposal from its DAO result: 30 TGas to apply it,
+/// update the Merkle tree and queue receipts, plus notifying up to `MAX_SUBSCRIBERS` subscribers.
+pub const GAS_FOR_PROPOSAL_RESULT: Gas = Gas(30_000_000_000_000
+    + MAX_SUBSCRIBERS as u64 * (MAX_SUBSCRIBER_GAS.0 + GAS_FOR_RESOLVE_TRANSFER.0));
+
+/// Most notifications skipped for lack of gas a registry keeps for a retry.
+pub const MAX_MISSED_NOTIFICATIONS: usize = 100;
@@ -0,0 +1,74 @@
+use near_sdk::json_types::U64;
+
+use crate::types::Vote;
+use crate::*;
+
+/// Who wrote the current data of a row, and when.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RowAttribution {
+    /// Proposal that wrote the row.
+    pub proposal_id: u64,
+    pub proposer: AccountId,
+    /// Accounts that voted to approve the proposal through this contract.
+    pub approvers: Vec<AccountId>,
+    pub approved_at: U64,
+}
+
+/// Contributions of an account to the registries.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContributorStats {
+    pub proposals_accepted: u64,
+    /// Proposals rejected, removed as spam or vetoed.
+    pub proposals_rejected: u64,
+    pub rows_authored: u64,
+}
+
+impl Contract {
+    pub(crate) fn internal_get_contributor(&self, account_id: &AccountId) -> ContributorStats {
+        self.contributors.get(account_id).unwrap_or_default()
+    }
+
+    /// Attributes rows written by the accepted proposal to its proposer and approvers,
+    /// and credits the proposer.
+    pub(crate) fn internal_record_accepted(
+        &mut self,
+        id: u64,
+        proposal: &Proposal,
+        change: Option<&AppliedChange>,
+    ) {
+        let mut stats = self.internal_get_contributor(&proposal.proposer);
+        stats.proposals_accepted += 1;
+        if let Some(change) = change {
+            let mut approvers: Vec<AccountId> = proposal
+                .votes
+                .iter()
+                .filter(|(_, vote)| matches!(vote, Vote::Approve))
+                .map(|(account_id, _)| account_id.clone())
+                .collect();
+            approvers.sort();
+            let attribution = RowAttribution {
+                proposal_id: id,
+                proposer: proposal.proposer.clone(),
+                approvers,
+                approved_at: U64(env::block_timestamp()),
+            };
+            if let Some(registry) = self.internal_find_registry_mut(&change.unique_identifier) {
+                for row in change.rows.iter().filter(|row| row.after.is_some()) {
+                    registry.set_row_attribution(row.unique_identifier, attribution.clone());
+                    stats.rows_authored += 1;
+                }
+            }
+        }
+        self.contributors.insert(&proposal.proposer, &stats);
+    }
+
+    /// Counts rejected, removed or vetoed proposal against its proposer.
+    pub(crate) fn internal_record_rejected(&mut self, proposal: &Proposal) {
+        let mut stats = self.internal_get_contributor(&proposal.proposer);
+        stats.proposals_rejected += 1;
+        self.contributors.insert(&proposal.proposer, &stats);
+    }
+}
@@ -0,0 +1,206 @@
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::{json_types::U128, PromiseOrValue};
+
+use crate::bounties::BountyInput;
+use crate::*;
+
+/// Staking tokens deposited by an account and how they are delegated.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stake {
+    /// Total amount of staking tokens deposited.
+    pub deposit_amount: U128,
+    /// Amounts delegated to given accounts.
+    pub delegated_amounts: Vec<(AccountId, U128)>,
+}
+
+impl Stake {
+    fn new() -> Self {
+        Self {
+            deposit_amount: U128(0),
+            delegated_amounts: vec![],
+        }
+    }
+
+    /// Amount of deposit that is not delegated to anyone.
+    fn available_amount(&self) -> Balance {
+        self.deposit_amount.0
+            - self
+                .delegated_amounts
+                .iter()
+                .map(|(_, amount)| amount.0)
+                .sum::<Balance>()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_get_stake(&self, account_id: &AccountId) -> Stake {
+        self.stakes.get(account_id).unwrap_or_else(Stake::new)
+    }
+
+    /// Changes voting power of given account and records a checkpoint of it.
+    fn internal_change_delegation(&mut self, account_id: &AccountId, amount: Balance, add: bool) {
+        let prev_amount = self.delegations.get(account_id).unwrap_or_default();
+        let new_amount = if add {
+            self.total_delegation_amount += amount;
+            prev_amount + amount
+        } else {
+            assert!(prev_amount >= amount, "ERR_INVALID_UNDELEGATE");
+            self.total_delegation_amount -= amount;
+            prev_amount - amount
+        };
+        self.delegations.insert(account_id, &new_amount);
+
+        let now = env::block_timestamp();
+        let mut checkpoints = self
+            .delegation_checkpoints
+            .get(account_id)
+            .unwrap_or_default();
+        match checkpoints.last_mut() {
+            Some((timestamp, amount)) if *timestamp == now => *amount = new_amount,
+            _ => checkpoints.push((now, new_amount)),
+        }
+        self.delegation_checkpoints.insert(account_id, &checkpoints);
+    }
+
+    /// Voting power of given account as it was before `timestamp`.
+    /// Delegations made in the same block as `timestamp` are not counted.
+    pub(crate) fn internal_voting_power_at(
+        &self,
+        account_id: &AccountId,
+        timestamp: u64,
+    ) -> Balance {
+        self.delegation_checkpoints
+            .get(account_id)
+            .unwrap_or_default()
+            .iter()
+            .rev()
+            .find(|(checkpoint, _)| *checkpoint < timestamp)
+            .map(|(_, amount)| *amount)
+            .unwrap_or_default()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets NEP-141 token used for voting power. Can only be set once.
+    pub fn set_staking_contract(&mut self, staking_id: AccountId) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "ERR_NOT_ALLOWED");
+        assert!(
+            self.staking_id.is_none(),
+            "ERR_STAKING_CONTRACT_CANT_CHANGE"
+        );
+        self.staking_id = Some(staking_id);
+    }
+
+    /// Delegates given amount of deposited staking tokens to given account.
+    pub fn delegate(&mut self, account_id: AccountId, amount: U128) {
+        let sender_id = env::predecessor_account_id();
+        let mut stake = self.internal_get_stake(&sender_id);
+        assert!(
+            stake.available_amount() >= amount.0,
+            "ERR_NOT_ENOUGH_AMOUNT"
+        );
+        match stake
+            .delegated_amounts
+            .iter_mut()
+            .find(|(delegate_id, _)| *delegate_id == account_id)
+        {
+            Some((_, delegated)) => delegated.0 += amount.0,
+            None => stake.delegated_amounts.push((account_id.clone(), amount)),
+        }
+        self.stakes.insert(&sender_id, &stake);
+        self.internal_change_delegation(&account_id, amount.0, true);
+    }
+
+    /// Removes given amount of delegation from given account.
+    pub fn undelegate(&mut self, account_id: AccountId, amount: U128) {
+        let sender_id = env::predecessor_account_id();
+        let mut stake = self.internal_get_stake(&sender_id);
+        let index = stake
+            .delegated_amounts
+            .iter()
+            .position(|(delegate_id, _)| *delegate_id == account_id)
+            .expect("ERR_NO_DELEGATION");
+        let delegated = stake.delegated_amounts[index].1 .0;
+        assert!(delegated >= amount.0, "ERR_NOT_ENOUGH_AMOUNT");
+        if delegated == amount.0 {
+            stake.delegated_amounts.remove(index);
+        } else {
+            stake.delegated_amounts[index].1 = U128(delegated - amount.0);
+        }
+        self.stakes.insert(&sender_id, &stake);
+        self.internal_change_delegation(&account_id, amount.0, false);
+    }
+
+    /// Withdraws given amount of undelegated staking tokens back to the caller.
+    pub fn withdraw(&mut self, amount: U128) -> PromiseOrValue<()> {
+        let sender_id = env::predecessor_account_id();
+        let mut stake = self.internal_get_stake(&sender_id);
+        assert!(
+            stake.available_amount() >= amount.0,
+            "ERR_NOT_ENOUGH_AMOUNT"
+        );
+        stake.deposit_amount.0 -= amount.0;
+        self.stakes.insert(&sender_id, &stake);
+        let staking_id = self.staking_id.clone().expect("ERR_NO_STAKING");
+        match self.internal_payout(
+            &Some(staking_id),
+            &sender_id,
+            amount.0,
+            "withdraw".to_string(),
+            &None,
+        ) {
+            PromiseOrValue::Promise(promise) => promise
+                .then(ext_self::on_withdraw_callback(
+                    sender_id,
+                    amount,
+                    env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_RESOLVE_TRANSFER,
+                ))
+                .into(),
+            value => value,
+        }
+    }
+
+    /// Returns tokens to the deposit if the transfer back failed.
+    #[private]
+    pub fn on_withdraw_callback(&mut self, account_id: AccountId, amount: U128) {
+        assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
+        if let PromiseResult::Failed = env::promise_result(0) {
+            let mut stake = self.internal_get_stake(&account_id);
+            stake.deposit_amount.0 += amount.0;
+            self.stakes.insert(&account_id, &stake);
+        }
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /// With empty `msg`, deposits staking tokens of the sender. They can be delegated afterwards.
+    /// Otherwise `msg` is a `BountyInput` funded with the transferred tokens.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        if !msg.is_empty() {
+            let bounty: BountyInput = serde_json::from_str(&msg).expect("ERR_INVALID_MSG");
+            let token = env::predecessor_account_id();
+            self.internal_add_bounty(&sender_id, Some(token), amount.0, bounty);
+            return PromiseOrValue::Value(U128(0));
+        }
+        assert_eq!(
+            Some(env::predecessor_account_id()),
+            self.staking_id,
+            "ERR_INVALID_TOKEN"
+        );
+        let mut stake = self.internal_get_stake(&sender_id);
+        stake.deposit_amount.0 += amount.0;
+        self.stakes.insert(&sender_id, &stake);
+        PromiseOrValue::Value(U128(0))
+    }
+}
@@ -0,0 +1,107 @@
+use near_sdk::json_types::{U128, U64};
+
+use crate::types::ProposalInput;
+use crate::*;
+
+/// Rules for challenging rows of a registry after they were applied.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ChallengePolicy {
+    /// How long after being applied a row can be challenged, in nanoseconds.
+    pub period: U64,
+    /// Bond the challenger stakes. Slashed if the row is kept.
+    pub bond: U128,
+    /// Paid to the challenger on top of the returned bond if the row is removed.
+    pub reward: U128,
+}
+
+impl Contract {
+    /// Closes dispute of a `DisputeRow` proposal that didn't remove the row,
+    /// returning the challenge bond if `refund`, otherwise slashing it.
+    pub(crate) fn internal_close_dispute(&mut self, proposal: &Proposal, refund: bool) {
+        if let ProposalKind::DisputeRow { row_id, bond } = &proposal.kind {
+            self.row_disputes
+                .remove(&(proposal.unique_identifier.clone(), *row_id));
+            self.reserved_amount -= bond.0;
+            if refund {
+                Promise::new(proposal.proposer.clone()).transfer(bond.0);
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets rules for challenging rows of given registry. `None` disables challenges.
+    /// Only for the registry owner.
+    pub fn set_registry_challenge_policy(
+        &mut self,
+        unique_identifier: AccountId,
+        challenge_policy: Option<ChallengePolicy>,
+    ) {
+        let registry = self.internal_owned_registry_mut(&unique_identifier);
+        registry.challenge_policy = challenge_policy;
+    }
+
+    /// Disputes row applied within the challenge period of its registry by submitting a
+    /// `DisputeRow` proposal. Attached deposit covers the challenge bond and the proposal bond.
+    /// If approved the row is removed and the challenger rewarded, otherwise the bond is slashed.
+    /// The bond is held until then, the reward is paid from the available balance.
+    /// Rows of token-gated registries can't be challenged.
+    #[payable]
+    pub fn challenge_row(
+        &mut self,
+        unique_identifier: AccountId,
+        row_id: u64,
+        description: String,
+    ) -> u64 {
+        let registry = self
+            .internal_find_registry(&unique_identifier)
+            .expect("ERR_NO_REGISTRY");
+        let policy = registry
+            .challenge_policy
+            .clone()
+            .expect("ERR_NOT_CHALLENGEABLE");
+        assert!(registry.token_gate.is_none(), "ERR_TOKEN_GATED");
+        let attribution = registry
+            .get_row_attribution(row_id)
+            .expect("ERR_NOT_CHALLENGEABLE");
+        assert!(
+            env::block_timestamp() < attribution.approved_at.0 + policy.period.0,
+            "ERR_CHALLENGE_PERIOD_OVER"
+        );
+        let key = (unique_identifier.clone(), row_id);
+        assert!(
+            !self.row_disputes.contains_key(&key),
+            "ERR_ALREADY_DISPUTED"
+        );
+        let deposit = env::attached_deposit();
+        assert!(deposit >= policy.bond.0, "ERR_MIN_BOND");
+        // The dispute is about the current data, it fails if the row changes meanwhile.
+        let expected_hashes = registry
+            .get_row_hash(row_id)
+            .map(|hash| (row_id, hash.clone()))
+            .into_iter()
+            .collect();
+        let proposal = ProposalInput {
+            owner: env::predecessor_account_id(),
+            description,
+            kind: ProposalKind::DisputeRow {
+                row_id,
+                bond: policy.bond,
+            },
+            column: vec![],
+            row: vec![],
+            unique_identifier,
+            expected_hashes,
+        };
+        let id = self.internal_add_proposal(
+            &env::predecessor_account_id(),
+            proposal,
+            deposit - policy.bond.0,
+        );
+        self.row_disputes.insert(&key, &id);
+        self.reserved_amount += policy.bond.0;
+        id
+    }
+}
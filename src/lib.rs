@@ -1,28 +1,107 @@
 extern crate core;
 
+mod bounties;
+mod canonical;
+mod commit_reveal;
 mod consts;
+mod contributors;
+mod delegation;
+mod disputes;
+mod lookup;
+pub mod merkle;
+mod migration;
+mod optimistic;
+mod policy;
 mod proposals;
+mod receipts;
+mod schema;
+mod strategies;
+mod subscriptions;
+mod token_gate;
 mod types;
 mod views;
 
-use crate::types::{Action, Proposal, ProposalOutput, ProposalStatus, VersionedProposal};
+use crate::bounties::Bounty;
+use crate::canonical::{canonical_json, content_hash};
+use crate::commit_reveal::{CommitRevealPolicy, VoteCommit};
+use crate::consts::{
+    DEFAULT_WITHDRAW_GRACE_PERIOD, DEFAULT_WITHDRAW_REFUND_BPS, GAS_FOR_RESOLVE_TRANSFER,
+    NO_DEPOSIT,
+};
+use crate::contributors::{ContributorStats, RowAttribution};
+use crate::delegation::Stake;
+use crate::disputes::ChallengePolicy;
+use crate::merkle::MerkleTree;
+use crate::optimistic::OptimisticPolicy;
+use crate::policy::DaoPolicy;
+use crate::receipts::{PendingReceipt, ReceiptPolicy};
+use crate::schema::{decode_row, decode_row_value, encode_row, Field, SchemaMigration};
+use crate::strategies::VoteStrategy;
+use crate::subscriptions::{change_summary, MissedNotification, Subscription};
+use crate::token_gate::TokenGate;
+use crate::types::{
+    convert_old_to_new_token, Action, AmendPolicy, AppliedChange, ArchivedProposal, CellChange,
+    LocalVote, PayoutStatus, Proposal, ProposalInput, ProposalKind, ProposalOutput, ProposalStatus,
+    VersionedProposal, WithdrawPolicy,
+};
 use near_sdk::{
     borsh::{self, BorshDeserialize, BorshSerialize},
-    collections::{LookupMap, TreeMap},
-    env, ext_contract, near_bindgen, serde_json,
+    collections::{LazyOption, LookupMap, TreeMap},
+    env, ext_contract,
+    json_types::{Base64VecU8, U128, U64},
+    near_bindgen, serde_json,
     serde_json::Value,
-    AccountId, Balance, BorshStorageKey, PanicOnDefault, PromiseResult,
+    AccountId, Balance, BorshStorageKey, PanicOnDefault, Promise, PromiseResult,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::{collections::HashMap, convert::TryFrom};
 
+pub use crate::types::RowOutput;
+
 #[ext_contract(ext_self)]
 pub trait ExtSelf {
     fn callback_promise_result() -> bool;
-    fn callback_add_proposal_result() -> u64;
+    fn callback_add_proposal_result(&mut self, proposal_id: u64) -> Option<u64>;
     fn on_proposal_callback(&mut self, proposal_id: u64) -> PromiseOrValue<()>;
-    fn proposal_result_callback(&mut self) -> ProposalOutput;
+    fn proposal_result_callback(&mut self, proposal_id: u64) -> ProposalOutput;
+    fn on_policy_callback(&mut self) -> DaoPolicy;
+    fn on_withdraw_callback(&mut self, account_id: AccountId, amount: U128);
+    fn on_relay_votes_callback(&mut self, proposal_id: u64) -> bool;
+    fn on_reconcile_votes_callback(&mut self, proposal_id: u64) -> HashMap<AccountId, Vote>;
+    fn on_withdraw_proposal_callback(&mut self, proposal_id: u64, status: ProposalStatus) -> U128;
+    fn on_remove_bounty_callback(&mut self, bounty_id: u64, times: u32) -> bool;
+    fn on_token_gate_proposal_callback(
+        &mut self,
+        proposer: AccountId,
+        proposal: ProposalInput,
+        bond: U128,
+    ) -> Option<u64>;
+    fn on_token_gate_vote_callback(
+        &mut self,
+        id: u64,
+        account_id: AccountId,
+        action: Action,
+        amount: U128,
+    );
+    fn on_token_gate_commit_callback(
+        &mut self,
+        id: u64,
+        account_id: AccountId,
+        hash: Base64VecU8,
+        deposit: U128,
+    ) -> bool;
+}
+
+/// Read API of registries for other contracts.
+#[ext_contract(ext_registry)]
+pub trait Registry {
+    /// Checks if `account_id` keys a row of the registry.
+    fn is_member(&self, unique_identifier: AccountId, account_id: AccountId) -> bool;
+    /// Row of the registry with given key.
+    fn get_row_by_key(&self, unique_identifier: AccountId, key: String) -> Option<RowOutput>;
+    /// Field of given row of the registry.
+    fn get_field(&self, unique_identifier: AccountId, row_id: u64, field: String) -> Option<Value>;
 }
 
 #[derive(BorshStorageKey, BorshSerialize)]
@@ -30,6 +109,17 @@ pub enum StorageKeys {
     Policy,
     Delegations,
     Proposals,
+    LockedBonds,
+    Stakes,
+    DelegationCheckpoints,
+    ArchivedProposals,
+    AppliedChanges,
+    Payouts,
+    Bounties,
+    Contributors,
+    RowDisputes,
+    VoteCommits,
+    PendingReceipts,
 }
 
 #[near_bindgen]
@@ -38,22 +128,62 @@ pub struct Contract {
     owner: AccountId,
     /// Amount of $NEAR locked for bonds.
     pub locked_amount: Balance,
+    /// Amount of $NEAR locked for bonds per proposer.
+    pub locked_bonds: LookupMap<AccountId, Balance>,
+    /// Amount of $NEAR held for bounties and challenge bonds until they are paid out or refunded.
+    pub reserved_amount: Balance,
     /// Vote staking contract id. That contract must have this account as owner.
     pub staking_id: Option<AccountId>,
     /// Delegated  token total amount.
     pub total_delegation_amount: Balance,
     /// Delegations per user.
     pub delegations: LookupMap<AccountId, Balance>,
+    /// History of delegations per user, as (timestamp, amount) after each change.
+    pub delegation_checkpoints: LookupMap<AccountId, Vec<(u64, Balance)>>,
+    /// Staking tokens deposited per user.
+    pub stakes: LookupMap<AccountId, Stake>,
     /// Last available id for the proposals.
     pub last_proposal_id: u64,
+    /// Proposals with lower ids were created before the state migration and are unknown to the DAO.
+    pub legacy_proposal_count: u64,
     /// Proposal map from ID to proposal information.
     pub proposals: TreeMap<u64, VersionedProposal>,
+    /// Compact records of finished proposals removed from `proposals`.
+    pub archived_proposals: LookupMap<u64, ArchivedProposal>,
+    /// Changes made to registries by applied proposals, kept to be able to revert them.
+    pub applied_changes: LookupMap<u64, AppliedChange>,
+    /// Payouts of approved proposals that are in flight or failed.
+    pub payouts: LookupMap<u64, PayoutStatus>,
+    /// Last available id for the bounties.
+    pub last_bounty_id: u64,
+    /// Bounties map from ID to bounty information.
+    pub bounties: LookupMap<u64, Bounty>,
+    /// Contributions per proposer.
+    pub contributors: LookupMap<AccountId, ContributorStats>,
+    /// Open `DisputeRow` proposal per registry row.
+    pub row_disputes: LookupMap<(AccountId, u64), u64>,
+    /// Committed votes per proposal to commit-reveal registries, until they are tallied.
+    pub vote_commits: LookupMap<u64, HashMap<AccountId, VoteCommit>>,
+    /// Receipt mints and burns waiting for `process_receipts`, by queue position.
+    pub pending_receipts: LookupMap<u64, PendingReceipt>,
+    /// Queue position of the oldest pending receipt.
+    pub first_pending_receipt_id: u64,
+    /// Next free queue position for pending receipts.
+    pub last_pending_receipt_id: u64,
+    /// Voting period of proposals. Follows the DAO policy if not set.
+    pub voting_period: Option<U64>,
     /// List of registries
     registries: HashMap<AccountId, Vec<RegistryData>>,
     /// AstroDao contract
     dao: AccountId,
+    /// Cached copy of the AstroDao policy.
+    policy: LazyOption<DaoPolicy>,
+    /// Rules for refunding bonds of withdrawn proposals.
+    withdraw_policy: WithdrawPolicy,
+    /// Rules for amending proposals.
+    amend_policy: AmendPolicy,
     /// Voting Power
-    voting_power: HashMap<u64, Vec<(AccountId, Balance, Action)>>,
+    voting_power: HashMap<u64, Vec<LocalVote>>,
 }
 
 #[near_bindgen]
@@ -69,6 +199,42 @@ pub struct RegistryData {
     row: Vec<Row>,
     #[serde(skip_serializing)]
     column: Vec<Column>,
+    /// Delay between approval and application of proposals, in nanoseconds.
+    timelock: Option<U64>,
+    /// Account allowed to veto queued proposals.
+    guardian: Option<AccountId>,
+    /// Rules for challenging applied rows. Rows can't be challenged if not set.
+    challenge_policy: Option<ChallengePolicy>,
+    /// Rules for approving proposals without waiting for the DAO.
+    optimistic_policy: Option<OptimisticPolicy>,
+    /// Rules for commit-reveal voting. Votes are public if not set.
+    commit_reveal_policy: Option<CommitRevealPolicy>,
+    /// How local votes on proposals to the registry are weighted.
+    vote_strategy: VoteStrategy,
+    /// Holdings required to propose or vote. Anyone can if not set.
+    token_gate: Option<TokenGate>,
+    /// Rules for minting NFT receipts for approved rows. No receipts if not set.
+    receipt_policy: Option<ReceiptPolicy>,
+    /// Field of row data that keys rows. Rows are keyed by their data if not set.
+    key_field: Option<String>,
+    /// Ids of rows per key, see `key_field`.
+    #[serde(skip)]
+    key_index: HashMap<String, Vec<u64>>,
+    /// Number of changes applied to the registry.
+    version: u64,
+    /// Contracts notified of changes.
+    subscribers: Vec<Subscription>,
+    /// Notifications skipped for lack of gas, oldest first.
+    #[serde(skip)]
+    missed_notifications: Vec<MissedNotification>,
+    /// Merkle tree over the rows.
+    #[serde(skip)]
+    merkle: MerkleTree,
+    /// Fields of row data. Rows fitting it are stored in a compact binary encoding.
+    schema: Option<Vec<Field>>,
+    /// Rows left to re-encode after the schema changed.
+    #[serde(skip)]
+    schema_migration: Option<SchemaMigration>,
 }
 
 #[near_bindgen]
@@ -79,6 +245,10 @@ pub struct Row {
     unique_identifier: u64,
     #[serde(skip_serializing)]
     data: Vec<u8>,
+    /// sha256 of the canonical JSON of the data.
+    content_hash: Base64VecU8,
+    /// Who wrote the current data. `None` for rows the registry was created with.
+    attribution: Option<RowAttribution>,
 }
 
 #[near_bindgen]
@@ -113,9 +283,12 @@ impl RegistryData {
         let mut row: Vec<Row> = Vec::new();
         for row_bytes in row_data {
             for identifier in &rows_identifiers {
+                let data = canonical_json(&row_bytes);
                 row.push(Row {
                     unique_identifier: *identifier,
-                    data: row_bytes.to_string().into_bytes(),
+                    content_hash: content_hash(&data),
+                    data,
+                    attribution: None,
                 });
             }
         }
@@ -124,7 +297,7 @@ impl RegistryData {
             for identifier in &columns_identifiers {
                 column.push(Column {
                     unique_identifier: *identifier,
-                    data: column_bytes.to_string().into_bytes(),
+                    data: canonical_json(&column_bytes),
                 });
             }
         }
@@ -132,14 +305,590 @@ impl RegistryData {
         for char in ".near".chars() {
             ident_name.push(char);
         }
-        Self {
+        let mut merkle = MerkleTree::default();
+        for row in &row {
+            merkle.push(row.unique_identifier, &row.data);
+        }
+        let mut registry = Self {
             dao,
             name,
             owner,
             unique_identifier: AccountId::try_from(ident_name.to_lowercase()).unwrap(),
             row,
             column,
+            timelock: None,
+            guardian: None,
+            challenge_policy: None,
+            optimistic_policy: None,
+            commit_reveal_policy: None,
+            vote_strategy: VoteStrategy::Linear,
+            token_gate: None,
+            receipt_policy: None,
+            key_field: None,
+            key_index: HashMap::new(),
+            version: 0,
+            subscribers: vec![],
+            missed_notifications: vec![],
+            merkle,
+            schema: None,
+            schema_migration: None,
+        };
+        registry.key_index = registry.build_key_index(None).unwrap_or_default();
+        registry
+    }
+}
+
+impl RegistryData {
+    /// Content hash of given row.
+    pub(crate) fn get_row_hash(&self, unique_identifier: u64) -> Option<&Base64VecU8> {
+        self.row
+            .iter()
+            .find(|row| row.unique_identifier == unique_identifier)
+            .map(|row| &row.content_hash)
+    }
+
+    /// Data of given row as canonical JSON, whatever encoding it is stored in.
+    pub(crate) fn row_json(&self, row: &Row) -> Vec<u8> {
+        decode_row(self.row_schema(row), &row.data)
+    }
+
+    /// Data of given row as a value, whatever encoding it is stored in.
+    pub(crate) fn row_value(&self, row: &Row) -> Value {
+        decode_row_value(self.row_schema(row), &row.data)
+    }
+
+    pub(crate) fn get_row(&self, unique_identifier: u64) -> Option<Vec<u8>> {
+        self.row
+            .iter()
+            .find(|row| row.unique_identifier == unique_identifier)
+            .map(|row| self.row_json(row))
+    }
+
+    /// Sets canonical JSON data of given row, removing it if `None`. Returns previous data.
+    /// Data is stored in the encoding of the registry schema, if any.
+    pub(crate) fn set_row(
+        &mut self,
+        unique_identifier: u64,
+        json: Option<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        let index = self
+            .row
+            .iter()
+            .position(|row| row.unique_identifier == unique_identifier);
+        let previous = index.map(|index| self.row_json(&self.row[index]));
+        self.merkle.set(unique_identifier, json.as_deref());
+        self.update_key_index(unique_identifier, previous.as_deref(), json.as_deref());
+        let data = json.clone().map(|json| match &self.schema {
+            Some(schema) => encode_row(schema, json),
+            None => json,
+        });
+        match (index, data, json) {
+            (Some(index), Some(data), Some(json)) => {
+                self.row[index].content_hash = content_hash(&json);
+                self.row[index].data = data;
+            }
+            (Some(index), None, _) => {
+                self.row.remove(index);
+            }
+            (None, Some(data), Some(json)) => {
+                self.row.push(Row {
+                    unique_identifier,
+                    content_hash: content_hash(&json),
+                    data,
+                    attribution: None,
+                });
+            }
+            _ => {}
+        }
+        self.finish_row_migration(unique_identifier);
+        previous
+    }
+
+    pub(crate) fn get_row_attribution(&self, unique_identifier: u64) -> Option<RowAttribution> {
+        self.row
+            .iter()
+            .find(|row| row.unique_identifier == unique_identifier)
+            .and_then(|row| row.attribution.clone())
+    }
+
+    pub(crate) fn set_row_attribution(
+        &mut self,
+        unique_identifier: u64,
+        attribution: RowAttribution,
+    ) {
+        if let Some(row) = self
+            .row
+            .iter_mut()
+            .find(|row| row.unique_identifier == unique_identifier)
+        {
+            row.attribution = Some(attribution);
+        }
+    }
+
+    pub(crate) fn get_column(&self, unique_identifier: u64) -> Option<Vec<u8>> {
+        self.column
+            .iter()
+            .find(|column| column.unique_identifier == unique_identifier)
+            .map(|column| column.data.clone())
+    }
+
+    /// Sets data of given column, removing it if `None`. Returns previous data.
+    pub(crate) fn set_column(
+        &mut self,
+        unique_identifier: u64,
+        data: Option<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        let index = self
+            .column
+            .iter()
+            .position(|column| column.unique_identifier == unique_identifier);
+        match (index, data) {
+            (Some(index), Some(data)) => {
+                Some(std::mem::replace(&mut self.column[index].data, data))
+            }
+            (Some(index), None) => Some(self.column.remove(index).data),
+            (None, Some(data)) => {
+                self.column.push(Column {
+                    unique_identifier,
+                    data,
+                });
+                None
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_find_registry(
+        &self,
+        unique_identifier: &AccountId,
+    ) -> Option<&RegistryData> {
+        self.registries
+            .values()
+            .flatten()
+            .find(|registry| registry.unique_identifier == *unique_identifier)
+    }
+
+    pub(crate) fn internal_find_registry_mut(
+        &mut self,
+        unique_identifier: &AccountId,
+    ) -> Option<&mut RegistryData> {
+        self.registries
+            .values_mut()
+            .flatten()
+            .find(|registry| registry.unique_identifier == *unique_identifier)
+    }
+
+    /// Registry with given identifier, for its owner only.
+    pub(crate) fn internal_owned_registry_mut(
+        &mut self,
+        unique_identifier: &AccountId,
+    ) -> &mut RegistryData {
+        let registry = self
+            .internal_find_registry_mut(unique_identifier)
+            .expect("ERR_NO_REGISTRY");
+        assert_eq!(
+            registry.owner,
+            env::predecessor_account_id(),
+            "ERR_NOT_ALLOWED"
+        );
+        registry
+    }
+
+    /// Removes registry with given identifier from the registries of its owner.
+    fn internal_remove_registry(
+        &mut self,
+        unique_identifier: &AccountId,
+    ) -> Result<RegistryData, &'static str> {
+        let owner = self
+            .internal_find_registry(unique_identifier)
+            .ok_or("ERR_NO_REGISTRY")?
+            .owner
+            .clone();
+        let registries = self.registries.get_mut(&owner).unwrap();
+        let index = registries
+            .iter()
+            .position(|registry| registry.unique_identifier == *unique_identifier)
+            .unwrap();
+        let registry = registries.remove(index);
+        if registries.is_empty() {
+            self.registries.remove(&owner);
+        }
+        Ok(registry)
+    }
+
+    /// Checks that the proposal can be applied to the current state of its registry.
+    pub(crate) fn internal_validate_proposal(
+        &self,
+        proposal: &Proposal,
+    ) -> Result<(), &'static str> {
+        let (takes_rows, takes_columns) = match proposal.kind {
+            ProposalKind::Vote => (true, true),
+            ProposalKind::AddRows | ProposalKind::UpdateRows | ProposalKind::BountyDone { .. } => {
+                (true, false)
+            }
+            ProposalKind::ChangeSchema { .. } => (false, true),
+            _ => (false, false),
+        };
+        if (!takes_rows && !proposal.new_rows.is_empty())
+            || (!takes_columns && !proposal.new_columns.is_empty())
+        {
+            return Err("ERR_UNEXPECTED_DATA");
+        }
+        let registry = || {
+            self.internal_find_registry(&proposal.unique_identifier)
+                .ok_or("ERR_NO_REGISTRY")
+        };
+        if !proposal.expected_hashes.is_empty() {
+            let registry = registry()?;
+            if proposal
+                .expected_hashes
+                .iter()
+                .any(|(id, hash)| registry.get_row_hash(*id) != Some(hash))
+            {
+                return Err("ERR_ROW_HASH_MISMATCH");
+            }
+        }
+        match &proposal.kind {
+            ProposalKind::Transfer {
+                token_id,
+                amount,
+                msg,
+                ..
+            } => {
+                if amount.0 == 0 {
+                    return Err("ERR_INVALID_AMOUNT");
+                }
+                if convert_old_to_new_token(token_id).is_none() && msg.is_some() {
+                    return Err("ERR_BASE_TOKEN_NO_MSG");
+                }
+            }
+            ProposalKind::SetStakingContract { .. } | ProposalKind::Vote => {}
+            ProposalKind::Revert { proposal_id } => {
+                let change = self
+                    .applied_changes
+                    .get(proposal_id)
+                    .ok_or("ERR_NOTHING_TO_REVERT")?;
+                if change.reverted_by.is_some() {
+                    return Err("ERR_ALREADY_REVERTED");
+                }
+            }
+            ProposalKind::BountyDone { bounty_id } => {
+                let bounty = self.bounties.get(bounty_id).ok_or("ERR_NO_BOUNTY")?;
+                if bounty.times == 0 {
+                    return Err("ERR_BOUNTY_CLAIMED");
+                }
+                if bounty.unique_identifier != proposal.unique_identifier {
+                    return Err("ERR_WRONG_REGISTRY");
+                }
+                let registry = registry()?;
+                if proposal.new_rows.is_empty() {
+                    return Err("ERR_NO_ROWS");
+                }
+                if proposal
+                    .new_rows
+                    .iter()
+                    .any(|row| registry.get_row(row.unique_identifier).is_some())
+                {
+                    return Err("ERR_ROW_EXISTS");
+                }
+                registry.check_row_keys(&proposal.new_rows)?;
+            }
+            ProposalKind::AddRows => {
+                let registry = registry()?;
+                if proposal.new_rows.is_empty() {
+                    return Err("ERR_NO_ROWS");
+                }
+                if proposal
+                    .new_rows
+                    .iter()
+                    .any(|row| registry.get_row(row.unique_identifier).is_some())
+                {
+                    return Err("ERR_ROW_EXISTS");
+                }
+                registry.check_row_keys(&proposal.new_rows)?;
+            }
+            ProposalKind::UpdateRows => {
+                let registry = registry()?;
+                if proposal.new_rows.is_empty() {
+                    return Err("ERR_NO_ROWS");
+                }
+                if proposal
+                    .new_rows
+                    .iter()
+                    .any(|row| registry.get_row(row.unique_identifier).is_none())
+                {
+                    return Err("ERR_NO_ROW");
+                }
+                registry.check_row_keys(&proposal.new_rows)?;
+            }
+            ProposalKind::DisputeRow { row_id, .. } => {
+                if registry()?.get_row(*row_id).is_none() {
+                    return Err("ERR_NO_ROW");
+                }
+            }
+            ProposalKind::DeleteRows { row_ids } => {
+                let registry = registry()?;
+                if row_ids.is_empty() {
+                    return Err("ERR_NO_ROWS");
+                }
+                if row_ids.iter().any(|id| registry.get_row(*id).is_none()) {
+                    return Err("ERR_NO_ROW");
+                }
+            }
+            ProposalKind::ChangeSchema { remove_columns } => {
+                let registry = registry()?;
+                if proposal.new_columns.is_empty() && remove_columns.is_empty() {
+                    return Err("ERR_NO_COLUMNS");
+                }
+                if remove_columns
+                    .iter()
+                    .any(|id| registry.get_column(*id).is_none())
+                {
+                    return Err("ERR_NO_COLUMN");
+                }
+            }
+            ProposalKind::RenameRegistry { name } => {
+                registry()?;
+                if name.is_empty() {
+                    return Err("ERR_INVALID_NAME");
+                }
+                if self
+                    .registries
+                    .values()
+                    .flatten()
+                    .any(|registry| registry.name == *name)
+                {
+                    return Err("ERR_NAME_EXISTS");
+                }
+            }
+            ProposalKind::TransferRegistryOwnership { new_owner } => {
+                if registry()?.owner == *new_owner {
+                    return Err("ERR_SAME_OWNER");
+                }
+            }
+            ProposalKind::ChangeRegistryDao { dao } => {
+                if registry()?.dao == dao.as_str() {
+                    return Err("ERR_SAME_DAO");
+                }
+            }
+            ProposalKind::DeleteRegistry => {
+                registry()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies approved proposal, or queues it if its registry has a timelock.
+    /// Returns the bond to the proposer.
+    pub(crate) fn internal_approve_proposal(&mut self, id: u64, proposal: &mut Proposal) {
+        let timelock = self
+            .internal_find_registry(&proposal.unique_identifier)
+            .and_then(|registry| registry.timelock);
+        match timelock {
+            Some(timelock) => {
+                proposal.status = ProposalStatus::Queued;
+                proposal.executable_at = Some(U64(env::block_timestamp() + timelock.0));
+            }
+            None => self.internal_apply_proposal(id, proposal),
+        }
+        self.internal_release_bond(proposal, true);
+    }
+
+    /// Applies changes of an approved proposal to its registry and records them.
+    /// If they can't be applied, the proposal is moved to `Failed`.
+    pub(crate) fn internal_apply_proposal(&mut self, id: u64, proposal: &mut Proposal) {
+        let result = self
+            .internal_validate_proposal(proposal)
+            .and_then(|_| self.internal_execute_kind(id, proposal));
+        match result {
+            Ok(change) => {
+                self.internal_record_accepted(id, proposal, change.as_ref());
+                self.internal_publish_change(id, proposal, change.as_ref());
+                if let Some(change) = change {
+                    self.internal_issue_receipts(&change);
+                    self.applied_changes.insert(&id, &change);
+                }
+                if self.internal_execute_payout(id, proposal) {
+                    self.internal_callback_proposal_success(proposal);
+                } else {
+                    self.internal_callback_proposal_fail(proposal);
+                }
+            }
+            Err(err) => {
+                env::log_str(err);
+                self.internal_close_dispute(proposal, true);
+                self.internal_callback_proposal_fail(proposal);
+            }
+        }
+    }
+
+    /// Executes validated proposal. Returns changes made to rows and columns, if any.
+    fn internal_execute_kind(
+        &mut self,
+        id: u64,
+        proposal: &Proposal,
+    ) -> Result<Option<AppliedChange>, &'static str> {
+        let unique_identifier = &proposal.unique_identifier;
+        let rows = || -> Vec<(u64, Option<Vec<u8>>)> {
+            proposal
+                .new_rows
+                .iter()
+                .map(|row| (row.unique_identifier, Some(row.data.clone())))
+                .collect()
+        };
+        let columns = || -> Vec<(u64, Option<Vec<u8>>)> {
+            proposal
+                .new_columns
+                .iter()
+                .map(|column| (column.unique_identifier, Some(column.data.clone())))
+                .collect()
+        };
+        match &proposal.kind {
+            ProposalKind::BountyDone { bounty_id } => {
+                let change = self.internal_change_cells(unique_identifier, rows(), vec![])?;
+                self.internal_claim_bounty(*bounty_id)?;
+                Ok(Some(change))
+            }
+            ProposalKind::Transfer { .. } | ProposalKind::SetStakingContract { .. } => Ok(None),
+            ProposalKind::Vote => self
+                .internal_change_cells(unique_identifier, rows(), columns())
+                .map(Some),
+            ProposalKind::AddRows | ProposalKind::UpdateRows => self
+                .internal_change_cells(unique_identifier, rows(), vec![])
+                .map(Some),
+            ProposalKind::DeleteRows { row_ids } => {
+                let rows = row_ids.iter().map(|id| (*id, None)).collect();
+                self.internal_change_cells(unique_identifier, rows, vec![])
+                    .map(Some)
+            }
+            ProposalKind::DisputeRow { row_id, .. } => {
+                self.row_disputes
+                    .remove(&(unique_identifier.clone(), *row_id));
+                self.internal_change_cells(unique_identifier, vec![(*row_id, None)], vec![])
+                    .map(Some)
+            }
+            ProposalKind::ChangeSchema { remove_columns } => {
+                let mut columns = columns();
+                columns.extend(remove_columns.iter().map(|id| (*id, None)));
+                self.internal_change_cells(unique_identifier, vec![], columns)
+                    .map(Some)
+            }
+            ProposalKind::Revert { proposal_id } => {
+                self.internal_revert_change(id, *proposal_id).map(Some)
+            }
+            ProposalKind::RenameRegistry { name } => {
+                let registry = self
+                    .internal_find_registry_mut(unique_identifier)
+                    .ok_or("ERR_NO_REGISTRY")?;
+                registry.name = name.clone();
+                Ok(None)
+            }
+            ProposalKind::TransferRegistryOwnership { new_owner } => {
+                let mut registry = self.internal_remove_registry(unique_identifier)?;
+                registry.owner = new_owner.clone();
+                self.registries
+                    .entry(new_owner.clone())
+                    .or_default()
+                    .push(registry);
+                Ok(None)
+            }
+            ProposalKind::ChangeRegistryDao { dao } => {
+                let registry = self
+                    .internal_find_registry_mut(unique_identifier)
+                    .ok_or("ERR_NO_REGISTRY")?;
+                registry.dao = dao.to_string();
+                Ok(None)
+            }
+            ProposalKind::DeleteRegistry => {
+                let mut registry = self.internal_remove_registry(unique_identifier)?;
+                self.internal_burn_receipts(&registry);
+                registry.publish_change(change_summary(id, proposal, None));
+                registry.refund_subscribers();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Sets given rows and columns of the registry, deleting those set to `None`.
+    fn internal_change_cells(
+        &mut self,
+        unique_identifier: &AccountId,
+        rows: Vec<(u64, Option<Vec<u8>>)>,
+        columns: Vec<(u64, Option<Vec<u8>>)>,
+    ) -> Result<AppliedChange, &'static str> {
+        let mut change = AppliedChange {
+            unique_identifier: unique_identifier.clone(),
+            rows: vec![],
+            columns: vec![],
+            reverted_by: None,
+        };
+        if rows.is_empty() && columns.is_empty() {
+            return Ok(change);
+        }
+        let registry = self
+            .internal_find_registry_mut(unique_identifier)
+            .ok_or("ERR_NO_REGISTRY")?;
+        for (id, after) in rows {
+            let before = registry.set_row(id, after.clone());
+            change.rows.push(CellChange {
+                unique_identifier: id,
+                before,
+                after,
+            });
+        }
+        for (id, after) in columns {
+            let before = registry.set_column(id, after.clone());
+            change.columns.push(CellChange {
+                unique_identifier: id,
+                before,
+                after,
+            });
         }
+        Ok(change)
+    }
+
+    /// Restores rows and columns changed by given applied proposal to their prior values.
+    /// Fails if any of them changed since.
+    fn internal_revert_change(
+        &mut self,
+        id: u64,
+        proposal_id: u64,
+    ) -> Result<AppliedChange, &'static str> {
+        let mut target = self
+            .applied_changes
+            .get(&proposal_id)
+            .ok_or("ERR_NOTHING_TO_REVERT")?;
+        let registry = self
+            .internal_find_registry_mut(&target.unique_identifier)
+            .ok_or("ERR_NO_REGISTRY")?;
+        let unchanged = target
+            .rows
+            .iter()
+            .all(|row| registry.get_row(row.unique_identifier) == row.after)
+            && target
+                .columns
+                .iter()
+                .all(|column| registry.get_column(column.unique_identifier) == column.after);
+        if !unchanged {
+            return Err("ERR_REVERT_CONFLICT");
+        }
+        for row in &target.rows {
+            registry.set_row(row.unique_identifier, row.before.clone());
+        }
+        for column in &target.columns {
+            registry.set_column(column.unique_identifier, column.before.clone());
+        }
+        let change = AppliedChange {
+            unique_identifier: target.unique_identifier.clone(),
+            rows: target.rows.iter().map(CellChange::inverse).collect(),
+            columns: target.columns.iter().map(CellChange::inverse).collect(),
+            reverted_by: None,
+        };
+        target.reverted_by = Some(id);
+        self.applied_changes.insert(&proposal_id, &target);
+        Ok(change)
     }
 }
 
@@ -148,22 +897,71 @@ impl Contract {
     #[init]
     pub fn init(owner_id: AccountId, dao: AccountId) -> Self {
         assert!(!env::state_exists(), "Already initialized");
+        Self::internal_new(owner_id, dao)
+    }
+
+    /// Fresh state with given owner and DAO.
+    pub(crate) fn internal_new(owner_id: AccountId, dao: AccountId) -> Self {
         Self {
-            owner: owner_id.clone(),
+            owner: owner_id,
             locked_amount: 0,
-            staking_id: Some(owner_id),
+            locked_bonds: LookupMap::new(StorageKeys::LockedBonds),
+            reserved_amount: 0,
+            staking_id: None,
             total_delegation_amount: 0,
             delegations: LookupMap::new(b"d".to_vec()),
+            delegation_checkpoints: LookupMap::new(StorageKeys::DelegationCheckpoints),
+            stakes: LookupMap::new(StorageKeys::Stakes),
             last_proposal_id: 0,
+            legacy_proposal_count: 0,
             proposals: TreeMap::new(b"p".to_vec()),
+            archived_proposals: LookupMap::new(StorageKeys::ArchivedProposals),
+            applied_changes: LookupMap::new(StorageKeys::AppliedChanges),
+            payouts: LookupMap::new(StorageKeys::Payouts),
+            last_bounty_id: 0,
+            bounties: LookupMap::new(StorageKeys::Bounties),
+            contributors: LookupMap::new(StorageKeys::Contributors),
+            row_disputes: LookupMap::new(StorageKeys::RowDisputes),
+            vote_commits: LookupMap::new(StorageKeys::VoteCommits),
+            pending_receipts: LookupMap::new(StorageKeys::PendingReceipts),
+            first_pending_receipt_id: 0,
+            last_pending_receipt_id: 0,
+            voting_period: None,
             registries: HashMap::new(),
             dao,
+            policy: LazyOption::new(StorageKeys::Policy, None),
+            withdraw_policy: WithdrawPolicy {
+                grace_period: U64(DEFAULT_WITHDRAW_GRACE_PERIOD),
+                refund_bps: DEFAULT_WITHDRAW_REFUND_BPS,
+            },
+            amend_policy: AmendPolicy {
+                preserve_votes: false,
+            },
             voting_power: HashMap::new(),
         }
     }
 
-    pub fn change_dao(&mut self, dao: AccountId) {
+    /// Changes AstroDao contract and refreshes the cached policy from it.
+    pub fn change_dao(&mut self, dao: AccountId) -> Promise {
         self.dao = dao;
+        self.policy.remove();
+        self.update_policy()
+    }
+
+    /// Fetches the current policy of the AstroDao contract into the local cache.
+    pub fn update_policy(&mut self) -> Promise {
+        Promise::new(self.dao.clone())
+            .function_call(
+                "get_policy".to_string(),
+                vec![],
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            )
+            .then(ext_self::on_policy_callback(
+                env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
     }
 
     pub fn new_registry(
@@ -179,11 +977,10 @@ impl Contract {
             env::panic_str("That`s name already exist");
         }
         for column in &column_data {
-            columns_identifiers
-                .push(self.check_identifier(column.to_string().as_bytes().len() as u64));
+            columns_identifiers.push(self.check_identifier(column.to_string().len() as u64));
         }
         for row in &row_data {
-            rows_identifiers.push(self.check_identifier(row.to_string().as_bytes().len() as u64));
+            rows_identifiers.push(self.check_identifier(row.to_string().len() as u64));
         }
         let mut vec = Vec::new();
         let registry_data = RegistryData::new(
@@ -207,53 +1004,17 @@ impl Contract {
         }
     }
 
-    #[private]
-    pub fn voting_change_registry(
+    /// Sets delay between approval and application of proposals to given registry,
+    /// and the guardian allowed to veto them meanwhile. Only for the registry owner.
+    pub fn set_registry_timelock(
         &mut self,
         unique_identifier: AccountId,
-        new_row_data: Vec<Value>,
-        new_column_data: Vec<Value>,
+        timelock: Option<U64>,
+        guardian: Option<AccountId>,
     ) {
-        let _ = self
-            .registries
-            .clone()
-            .iter()
-            .map(|(_, registry_data)| {
-                for data in registry_data {
-                    if data.unique_identifier == unique_identifier {
-                        let mut columns_identifiers = Vec::new();
-                        for identifier in &data.column {
-                            columns_identifiers.push(identifier.unique_identifier)
-                        }
-                        let mut rows_identifiers = Vec::new();
-                        for identifier in &data.row {
-                            rows_identifiers.push(identifier.unique_identifier)
-                        }
-                        let new_data = RegistryData::new(
-                            data.name.clone(),
-                            new_row_data.clone(),
-                            new_column_data.clone(),
-                            data.owner.clone(),
-                            data.dao.clone(),
-                            columns_identifiers,
-                            rows_identifiers,
-                        );
-                        let mut value = 0;
-                        self.registries.entry(data.owner.clone()).and_modify(|x| {
-                            for i in x.clone() {
-                                if i.unique_identifier == unique_identifier {
-                                    x.remove(value);
-                                    x.insert(0, new_data.clone());
-                                    break;
-                                } else {
-                                    value += 1;
-                                }
-                            }
-                        });
-                    }
-                }
-            })
-            .collect::<()>();
+        let registry = self.internal_owned_registry_mut(&unique_identifier);
+        registry.timelock = timelock;
+        registry.guardian = guardian;
     }
 
     #[private]
@@ -277,14 +1038,15 @@ impl Contract {
     }
 
     #[private]
-    pub fn callback_add_proposal_result(&mut self) -> u64 {
+    pub fn on_policy_callback(&mut self) -> DaoPolicy {
         assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
             PromiseResult::Successful(val) => {
-                if let Ok(result) = near_sdk::serde_json::from_slice::<u64>(&val) {
-                    self.last_proposal_id = result;
-                    self.last_proposal_id
+                if let Ok(mut policy) = near_sdk::serde_json::from_slice::<DaoPolicy>(&val) {
+                    policy.fetched_at = Some(U64(env::block_timestamp()));
+                    self.policy.set(&policy);
+                    policy
                 } else {
                     env::panic_str("ERR_WRONG_VAL_RECEIVED")
                 }
@@ -294,67 +1056,89 @@ impl Contract {
     }
 
     #[private]
-    pub fn proposal_result_callback(&mut self) -> (ProposalOutput, ProposalStatus) {
+    pub fn callback_add_proposal_result(&mut self, proposal_id: u64) -> Option<u64> {
+        assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
+        let mut proposal: Proposal = self
+            .proposals
+            .get(&proposal_id)
+            .expect("ERR_NO_PROPOSAL")
+            .into();
+        let dao_proposal_id = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(val) => {
+                if let Ok(result) = near_sdk::serde_json::from_slice::<u64>(&val) {
+                    proposal.dao_proposal_id = Some(result);
+                    Some(result)
+                } else {
+                    env::panic_str("ERR_WRONG_VAL_RECEIVED")
+                }
+            }
+            PromiseResult::Failed => {
+                // The DAO never took the bond, so it is still on this account.
+                self.internal_close_dispute(&proposal, true);
+                self.internal_callback_proposal_fail(&mut proposal);
+                self.internal_release_bond(&mut proposal, true);
+                None
+            }
+        };
+        self.proposals
+            .insert(&proposal_id, &VersionedProposal::V2(proposal));
+        dao_proposal_id
+    }
+
+    #[private]
+    pub fn proposal_result_callback(
+        &mut self,
+        proposal_id: u64,
+    ) -> (ProposalOutput, ProposalStatus) {
         assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
             PromiseResult::Successful(val) => {
                 if let Ok(result) = near_sdk::serde_json::from_slice::<ProposalOutput>(&val) {
+                    let mut proposal: Proposal = self
+                        .proposals
+                        .get(&proposal_id)
+                        .expect("ERR_NO_PROPOSAL")
+                        .into();
+                    assert!(
+                        matches!(
+                            proposal.status,
+                            ProposalStatus::InProgress | ProposalStatus::Failed
+                        ) && !self.payouts.contains_key(&proposal_id),
+                        "ERR_PROPOSAL_ALREADY_FINALIZED"
+                    );
                     match result.status {
                         ProposalStatus::InProgress => env::panic_str("PROPOSAL_IN_PROGRESS"),
                         ProposalStatus::Approved => {
-                            let mut proposal: Proposal = self
-                                .proposals
-                                .get(&result.id.clone())
-                                .expect("ERR_NO_PROPOSAL")
-                                .into();
-                            let values = self.get_registry(proposal.clone());
-                            self.voting_change_registry(
-                                self.get_identifier(proposal.new_registries.clone()),
-                                vec![values.0],
-                                vec![values.1],
-                            );
-                            self.internal_callback_proposal_success(&mut proposal);
-                            (result.clone(), result.status)
+                            self.internal_approve_proposal(proposal_id, &mut proposal);
                         }
-                        ProposalStatus::Rejected => {
-                            let mut proposal: Proposal = self
-                                .proposals
-                                .get(&result.id.clone())
-                                .expect("ERR_NO_PROPOSAL")
-                                .into();
-                            self.internal_callback_proposal_fail(&mut proposal);
-                            (result.clone(), result.status)
+                        ProposalStatus::Rejected | ProposalStatus::Expired => {
+                            let rejected = result.status == ProposalStatus::Rejected;
+                            if rejected {
+                                self.internal_record_rejected(&proposal);
+                            }
+                            self.internal_close_dispute(&proposal, !rejected);
+                            proposal.status = result.status.clone();
+                            self.internal_release_bond(&mut proposal, true);
                         }
                         ProposalStatus::Removed => {
-                            let mut proposal: Proposal = self
-                                .proposals
-                                .get(&result.id)
-                                .expect("ERR_NO_PROPOSAL")
-                                .into();
-                            self.internal_callback_proposal_fail(&mut proposal);
-                            (result.clone(), result.status)
-                        }
-                        ProposalStatus::Expired => {
-                            let mut proposal: Proposal = self
-                                .proposals
-                                .get(&result.id)
-                                .expect("ERR_NO_PROPOSAL")
-                                .into();
-                            self.internal_callback_proposal_fail(&mut proposal);
-                            (result.clone(), result.status)
+                            self.internal_record_rejected(&proposal);
+                            self.internal_close_dispute(&proposal, false);
+                            proposal.status = ProposalStatus::Removed;
+                            self.internal_release_bond(&mut proposal, false);
                         }
-                        ProposalStatus::Moved => unreachable!(),
+                        ProposalStatus::Moved
+                        | ProposalStatus::Cancelled
+                        | ProposalStatus::Queued
+                        | ProposalStatus::Vetoed => unreachable!(),
                         ProposalStatus::Failed => {
-                            let mut proposal: Proposal = self
-                                .proposals
-                                .get(&result.id)
-                                .expect("ERR_NO_PROPOSAL")
-                                .into();
                             self.internal_callback_proposal_fail(&mut proposal);
-                            (result.clone(), result.status)
                         }
                     }
+                    self.proposals
+                        .insert(&proposal_id, &VersionedProposal::V2(proposal));
+                    (result.clone(), result.status)
                 } else {
                     env::panic_str("ERR_WRONG_VAL_RECEIVED")
                 }
@@ -367,10 +1151,18 @@ impl Contract {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bounties::BountyInput;
+    use crate::consts::{ONE_NEAR, SUBSCRIPTION_DEPOSIT, VOTE_COMMIT_DEPOSIT};
+    use crate::migration::{ContractV1, RegistryDataV1, RowV1};
+    use crate::schema::FieldKind;
+    use crate::strategies::VoteStrategy;
+    use crate::types::{ProposalKind, ProposalV1, Vote};
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
     use near_sdk::serde::export::TryFrom;
     use near_sdk::serde_json::json;
     use near_sdk::test_utils::VMContextBuilder;
     use near_sdk::testing_env;
+    use near_sdk::PromiseOrValue;
     use std::str::FromStr;
 
     fn alice() -> AccountId {
@@ -381,6 +1173,10 @@ mod tests {
         AccountId::try_from("bob.near".to_string()).unwrap()
     }
 
+    fn registry() -> AccountId {
+        AccountId::try_from("registry.near".to_string()).unwrap()
+    }
+
     // part of writing unit tests is setting up a mock context
     // provide a `predecessor` here, it'll modify the default context
     fn get_context(predecessor: AccountId) -> VMContextBuilder {
@@ -434,13 +1230,38 @@ mod tests {
         let mut contract = Contract {
             owner: alice(),
             locked_amount: 0,
+            locked_bonds: LookupMap::new(StorageKeys::LockedBonds),
+            reserved_amount: 0,
             staking_id: None,
             total_delegation_amount: 0,
             delegations: LookupMap::new(b"d"),
+            delegation_checkpoints: LookupMap::new(StorageKeys::DelegationCheckpoints),
+            stakes: LookupMap::new(StorageKeys::Stakes),
             last_proposal_id: 0,
+            legacy_proposal_count: 0,
             proposals: TreeMap::new(b"p"),
+            archived_proposals: LookupMap::new(StorageKeys::ArchivedProposals),
+            applied_changes: LookupMap::new(StorageKeys::AppliedChanges),
+            payouts: LookupMap::new(StorageKeys::Payouts),
+            last_bounty_id: 0,
+            bounties: LookupMap::new(StorageKeys::Bounties),
+            contributors: LookupMap::new(StorageKeys::Contributors),
+            row_disputes: LookupMap::new(StorageKeys::RowDisputes),
+            vote_commits: LookupMap::new(StorageKeys::VoteCommits),
+            pending_receipts: LookupMap::new(StorageKeys::PendingReceipts),
+            first_pending_receipt_id: 0,
+            last_pending_receipt_id: 0,
+            voting_period: None,
             registries: Default::default(),
             dao: bob(),
+            policy: LazyOption::new(StorageKeys::Policy, None),
+            withdraw_policy: WithdrawPolicy {
+                grace_period: U64(DEFAULT_WITHDRAW_GRACE_PERIOD),
+                refund_bps: DEFAULT_WITHDRAW_REFUND_BPS,
+            },
+            amend_policy: AmendPolicy {
+                preserve_votes: false,
+            },
             voting_power: HashMap::new(),
         };
 
@@ -465,67 +1286,1702 @@ mod tests {
         println!("{:?}", result);
     }
 
+    // set up a context where `registry()` is both the contract and the caller,
+    // as required by `#[private]` callbacks, with given promise result
+    fn set_callback_context(context: &mut VMContextBuilder, result: PromiseResult) {
+        testing_env!(
+            context.predecessor_account_id(registry()).build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    fn dao_proposal_output(id: u64, status: &str) -> PromiseResult {
+        let output = json!({
+            "id": id,
+            "proposer": registry(),
+            "description": "test",
+            "kind": "Vote",
+            "status": status,
+            "vote_counts": {},
+            "votes": {},
+            "submission_time": "0",
+        });
+        PromiseResult::Successful(output.to_string().into_bytes())
+    }
+
+    fn proposal_input(kind: ProposalKind) -> ProposalInput {
+        ProposalInput {
+            owner: bob(),
+            description: "test".to_string(),
+            kind,
+            column: vec![],
+            row: vec![],
+            unique_identifier: AccountId::from_str("testname.near").unwrap(),
+            expected_hashes: HashMap::new(),
+        }
+    }
+
+    /// Id of a proposal added right away, i.e. not to a token-gated registry.
+    fn added(result: PromiseOrValue<Option<u64>>) -> u64 {
+        match result {
+            PromiseOrValue::Value(Some(id)) => id,
+            _ => panic!("proposal not added"),
+        }
+    }
+
+    fn create_proposal(context: &mut VMContextBuilder, contract: &mut Contract) -> u64 {
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let id = added(contract.add_proposal(proposal_input(ProposalKind::Vote)));
+        set_callback_context(
+            context,
+            PromiseResult::Successful(json!(7).to_string().into_bytes()),
+        );
+        assert_eq!(contract.callback_add_proposal_result(id), Some(7));
+        id
+    }
+
     #[test]
-    fn test_voting_change_registry() {
-        // set up the mock context into the testing environment
+    fn test_proposal_bond() {
+        let mut context = get_context(registry());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+
+        let approved = create_proposal(&mut context, &mut contract);
+        let removed = create_proposal(&mut context, &mut contract);
+        assert_eq!(contract.get_locked_bond(bob()).0, 2 * ONE_NEAR);
+        assert_eq!(contract.get_locked_amount().0, 2 * ONE_NEAR);
+
+        set_callback_context(&mut context, dao_proposal_output(7, "Approved"));
+        contract.proposal_result_callback(approved);
+        assert_eq!(contract.get_proposal(approved)[0]["status"], "Approved");
+        assert_eq!(contract.get_locked_bond(bob()).0, ONE_NEAR);
+
+        set_callback_context(&mut context, dao_proposal_output(7, "Removed"));
+        contract.proposal_result_callback(removed);
+        assert_eq!(contract.get_proposal(removed)[0]["status"], "Removed");
+        assert_eq!(contract.get_locked_bond(bob()).0, 0);
+        assert_eq!(contract.get_locked_amount().0, 0);
+    }
+
+    #[test]
+    fn test_proposal_bond_too_small() {
+        let mut context = get_context(bob());
+        testing_env!(context.attached_deposit(ONE_NEAR - 1).build());
+        let contract = Contract::init(alice(), bob());
+        let checked = contract.internal_check_proposal(
+            &bob(),
+            proposal_input(ProposalKind::Vote),
+            ONE_NEAR - 1,
+        );
+        assert_eq!(checked.err(), Some("ERR_MIN_BOND"));
+    }
+
+    fn dao_policy(proposal_bond: Balance, permissions: &[&str]) -> PromiseResult {
+        let policy = json!({
+            "roles": [{
+                "name": "all",
+                "kind": "Everyone",
+                "permissions": permissions,
+                "vote_policy": {},
+            }],
+            "default_vote_policy": {
+                "weight_kind": "RoleWeight",
+                "quorum": "0",
+                "threshold": [1, 2],
+            },
+            "proposal_bond": proposal_bond.to_string(),
+            "proposal_period": "1000",
+            "bounty_bond": "0",
+            "bounty_forgiveness_period": "0",
+        });
+        PromiseResult::Successful(policy.to_string().into_bytes())
+    }
+
+    #[test]
+    fn test_policy_cache() {
+        let mut context = get_context(registry());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+
+        set_callback_context(
+            &mut context,
+            dao_policy(ONE_NEAR / 2, &["vote:AddProposal"]),
+        );
+        contract.on_policy_callback();
+        assert_eq!(contract.get_policy().unwrap().proposal_bond.0, ONE_NEAR / 2);
+
+        let id = create_proposal(&mut context, &mut contract);
+        assert_eq!(contract.get_proposal(id)[0]["voting_deadline"], "1000");
+    }
+
+    #[test]
+    fn test_policy_rejects_proposal_kind() {
+        let mut context = get_context(registry());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+
+        set_callback_context(&mut context, dao_policy(0, &["transfer:AddProposal"]));
+        contract.on_policy_callback();
+        let checked =
+            contract.internal_check_proposal(&bob(), proposal_input(ProposalKind::Vote), ONE_NEAR);
+        assert_eq!(checked.err(), Some("ERR_PERMISSION_DENIED"));
+    }
+
+    fn token() -> AccountId {
+        AccountId::try_from("token.near".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_delegated_voting_power() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        contract.set_staking_contract(token());
+
+        testing_env!(context.predecessor_account_id(token()).build());
+        contract.ft_on_transfer(bob(), U128(100), "".to_string());
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.delegate(bob(), U128(60));
+        contract.delegate(alice(), U128(40));
+        assert_eq!(contract.get_total_delegation_amount().0, 100);
+
+        testing_env!(context.block_timestamp(10).build());
+        let id = create_proposal(&mut context, &mut contract);
+        // Delegations after submission don't change the voting power.
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.undelegate(alice(), U128(40));
+        contract.delegate(bob(), U128(40));
+        contract.act_proposal(id, Action::VoteApprove, U128(0));
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.act_proposal(id, Action::VoteReject, U128(0));
+
+        let tally = contract.get_proposal_tally(id);
+        assert_eq!(tally.approve.0, 60);
+        assert_eq!(tally.reject.0, 40);
+        assert_eq!(tally.total.0, 100);
+    }
+
+    #[test]
+    fn test_vote_mirroring() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        contract.set_staking_contract(token());
+        testing_env!(context.predecessor_account_id(token()).build());
+        contract.ft_on_transfer(bob(), U128(100), "".to_string());
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.delegate(bob(), U128(60));
+        contract.delegate(alice(), U128(40));
+        testing_env!(context.block_timestamp(10).build());
+        let id = create_proposal(&mut context, &mut contract);
+
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.act_proposal(id, Action::VoteReject, U128(0));
+        let proposal: Proposal = contract.proposals.get(&id).unwrap().into();
+        assert_eq!(contract.internal_local_decision(id, &proposal), None);
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.act_proposal(id, Action::VoteApprove, U128(0));
+
+        // The DAO only gets the aggregated vote of this contract.
+        contract.relay_votes(id);
+        set_callback_context(&mut context, PromiseResult::Failed);
+        assert!(!contract.on_relay_votes_callback(id));
+        assert!(contract
+            .get_proposal_voters(id)
+            .iter()
+            .all(|voter| voter.vote == Vote::InProgress));
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.relay_votes(id);
+        set_callback_context(&mut context, PromiseResult::Successful(vec![]));
+        assert!(contract.on_relay_votes_callback(id));
+        assert_eq!(
+            contract.get_proposal(id)[0]["vote_counts"]["delegated"],
+            json!(["60", "40", "0"])
+        );
+        assert_eq!(
+            contract.get_proposal(id)[0]["relayed_vote"],
+            json!("VoteApprove")
+        );
+
+        // The DAO lost the relayed vote but got one directly from token.
+        let output = json!({
+            "id": 7,
+            "proposer": registry(),
+            "description": "test",
+            "kind": "Vote",
+            "status": "InProgress",
+            "vote_counts": { "council": [0, 1, 0] },
+            "votes": { "token.near": "Reject" },
+            "submission_time": "0",
+        });
+        set_callback_context(
+            &mut context,
+            PromiseResult::Successful(output.to_string().into_bytes()),
+        );
+        let votes = contract.on_reconcile_votes_callback(id);
+        assert_eq!(votes.len(), 3);
+        assert_eq!(votes[&token()], Vote::Reject);
+        assert_eq!(votes[&bob()], Vote::InProgress);
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.relay_votes(id);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_VOTING_POWER")]
+    fn test_vote_without_voting_power() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        delegate_voting_power(&mut context, &mut contract, &[(alice(), 10)]);
+        let id = create_proposal(&mut context, &mut contract);
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.act_proposal(id, Action::VoteApprove, U128(0));
+        testing_env!(context.predecessor_account_id(token()).build());
+        contract.act_proposal(id, Action::VoteApprove, U128(0));
+    }
+
+    #[test]
+    fn test_withdraw_proposal() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        delegate_voting_power(&mut context, &mut contract, &[(alice(), 10)]);
+
+        let fresh = create_proposal(&mut context, &mut contract);
+        let voted = create_proposal(&mut context, &mut contract);
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.act_proposal(voted, Action::VoteApprove, U128(0));
+
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.withdraw_proposal(fresh);
+        contract.withdraw_proposal(voted);
+        // The proposal stays in progress until the DAO removed it.
+        set_callback_context(&mut context, PromiseResult::Failed);
+        assert_eq!(
+            contract
+                .on_withdraw_proposal_callback(voted, ProposalStatus::Cancelled)
+                .0,
+            0
+        );
+        assert_eq!(contract.get_proposal(voted)[0]["status"], "InProgress");
+        assert_eq!(contract.get_locked_bond(bob()).0, 2 * ONE_NEAR);
+
+        set_callback_context(&mut context, PromiseResult::Successful(vec![]));
+        assert_eq!(
+            contract
+                .on_withdraw_proposal_callback(fresh, ProposalStatus::Cancelled)
+                .0,
+            ONE_NEAR
+        );
+        assert_eq!(
+            contract
+                .on_withdraw_proposal_callback(voted, ProposalStatus::Cancelled)
+                .0,
+            ONE_NEAR / 2
+        );
+        assert_eq!(contract.get_proposal(voted)[0]["status"], "Cancelled");
+        assert_eq!(contract.get_locked_bond(bob()).0, 0);
+        assert_eq!(contract.get_locked_amount().0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PROPOSAL_NOT_IN_DAO")]
+    fn test_withdraw_pending_proposal() {
+        let mut context = get_context(bob());
+        testing_env!(context
+            .current_account_id(registry())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let mut contract = Contract::init(alice(), bob());
+        let id = added(contract.add_proposal(proposal_input(ProposalKind::Vote)));
+        contract.withdraw_proposal(id);
+    }
+
+    #[test]
+    fn test_amend_proposal() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        contract.set_amend_policy(AmendPolicy {
+            preserve_votes: true,
+        });
+        delegate_voting_power(&mut context, &mut contract, &[(alice(), 10)]);
+
+        let id = create_proposal(&mut context, &mut contract);
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.act_proposal(id, Action::VoteApprove, U128(0));
+
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let revision = contract.amend_proposal(id, proposal_input(ProposalKind::Vote));
+        assert_eq!(contract.get_proposal(id)[0]["status"], "InProgress");
+        set_callback_context(&mut context, PromiseResult::Successful(vec![]));
+        contract.on_withdraw_proposal_callback(id, ProposalStatus::Moved);
+        assert_eq!(contract.get_proposal(id)[0]["status"], "Moved");
+        assert_eq!(
+            contract.get_proposal(revision)[0]["revisions"],
+            json!([id, revision])
+        );
+        // Carried over votes are pending until they are relayed for the revision.
+        assert_eq!(
+            contract.get_proposal_voters(revision)[0].vote,
+            Vote::InProgress
+        );
+        assert_eq!(
+            contract.get_proposal(revision)[0]["vote_counts"]["delegated"],
+            json!(["0", "0", "0"])
+        );
+        assert_eq!(contract.get_locked_bond(bob()).0, ONE_NEAR);
+
+        // Revisions stay linked once the original is archived.
+        assert_eq!(contract.cleanup_proposals(0, 10), vec![id]);
+        assert_eq!(contract.get_revisions(revision), vec![id, revision]);
+        assert_eq!(contract.get_revisions(id), vec![id, revision]);
+    }
+
+    #[test]
+    fn test_expire_and_cleanup_proposals() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        contract.set_voting_period(Some(U64(100)));
+
+        let expiring = create_proposal(&mut context, &mut contract);
+        let cancelled = create_proposal(&mut context, &mut contract);
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.withdraw_proposal(cancelled);
+        set_callback_context(&mut context, PromiseResult::Successful(vec![]));
+        contract.on_withdraw_proposal_callback(cancelled, ProposalStatus::Cancelled);
+
+        testing_env!(context.block_timestamp(50).build());
+        let in_progress = create_proposal(&mut context, &mut contract);
+        assert_eq!(contract.cleanup_proposals(0, 10), vec![cancelled]);
+
+        testing_env!(context
+            .block_timestamp(100)
+            .predecessor_account_id(alice())
+            .build());
+        contract.act_proposal(expiring, Action::VoteApprove, U128(0));
+        assert_eq!(contract.get_proposal(expiring)[0]["status"], "Expired");
+        assert_eq!(contract.get_locked_bond(bob()).0, ONE_NEAR);
+
+        assert_eq!(contract.cleanup_proposals(0, 10), vec![expiring]);
+        assert_eq!(
+            contract.get_proposal(in_progress)[0]["status"],
+            "InProgress"
+        );
+        assert_eq!(
+            contract.get_archived_proposal(expiring).unwrap().status,
+            ProposalStatus::Expired
+        );
+    }
+
+    #[test]
+    fn test_registry_timelock() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        contract.set_registry_timelock(identifier.clone(), Some(U64(100)), Some(alice()));
+
+        let executed = create_proposal(&mut context, &mut contract);
+        let vetoed = create_proposal(&mut context, &mut contract);
+        set_callback_context(&mut context, dao_proposal_output(7, "Approved"));
+        contract.proposal_result_callback(executed);
+        contract.proposal_result_callback(vetoed);
+        assert_eq!(contract.get_proposal(executed)[0]["status"], "Queued");
+        assert_eq!(contract.get_proposal(executed)[0]["executable_at"], "100");
+
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.veto_proposal(vetoed);
+        assert_eq!(contract.get_proposal(vetoed)[0]["status"], "Vetoed");
+
+        testing_env!(context.block_timestamp(100).build());
+        contract.execute_proposal(executed);
+        assert_eq!(contract.get_proposal(executed)[0]["status"], "Approved");
+    }
+
+    #[test]
+    fn test_revert_proposal() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        let add_row = |contract: &mut Contract, context: &mut VMContextBuilder, data: &str| {
+            testing_env!(context
+                .predecessor_account_id(bob())
+                .attached_deposit(ONE_NEAR)
+                .build());
+            let mut input = proposal_input(ProposalKind::Vote);
+            input.row = vec![json!({"unique_identifier": 1, "data": data})];
+            let id = added(contract.add_proposal(input));
+            set_callback_context(context, dao_proposal_output(7, "Approved"));
+            contract.proposal_result_callback(id);
+            id
+        };
+        let revert = |contract: &mut Contract, context: &mut VMContextBuilder, target: u64| {
+            testing_env!(context
+                .predecessor_account_id(bob())
+                .attached_deposit(ONE_NEAR)
+                .build());
+            let id = added(contract.add_proposal(proposal_input(ProposalKind::Revert {
+                proposal_id: target,
+            })));
+            set_callback_context(context, dao_proposal_output(7, "Approved"));
+            contract.proposal_result_callback(id);
+            id
+        };
+        let row = |contract: &Contract| {
+            contract
+                .internal_find_registry(&identifier.clone())
+                .unwrap()
+                .get_row(1)
+        };
+
+        let first = add_row(&mut contract, &mut context, "first");
+        let second = add_row(&mut contract, &mut context, "second");
+        assert_eq!(row(&contract), Some(b"\"second\"".to_vec()));
+
+        // The row changed since `first` was applied.
+        let conflicting = revert(&mut contract, &mut context, first);
+        assert_eq!(contract.get_proposal(conflicting)[0]["status"], "Failed");
+
+        let reverted = revert(&mut contract, &mut context, second);
+        assert_eq!(contract.get_proposal(reverted)[0]["status"], "Approved");
+        assert_eq!(row(&contract), Some(b"\"first\"".to_vec()));
+        assert_eq!(
+            contract.get_applied_change(second).unwrap()["reverted_by"],
+            reverted
+        );
+
+        let reverted = revert(&mut contract, &mut context, first);
+        assert_eq!(contract.get_proposal(reverted)[0]["status"], "Approved");
+        assert_eq!(row(&contract), None);
+    }
+
+    fn approve_proposal(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        kind: ProposalKind,
+        row: Vec<Value>,
+    ) -> u64 {
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let mut input = proposal_input(kind);
+        input.row = row;
+        let id = added(contract.add_proposal(input));
+        set_callback_context(context, dao_proposal_output(7, "Approved"));
+        contract.proposal_result_callback(id);
+        id
+    }
+
+    /// Stakes and self-delegates given amounts with `token()` as the staking contract,
+    /// then moves the clock past the delegations so they count for new proposals.
+    fn delegate_voting_power(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        stakes: &[(AccountId, Balance)],
+    ) {
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .attached_deposit(0)
+            .build());
+        contract.set_staking_contract(token());
+        for (account_id, amount) in stakes {
+            testing_env!(context.predecessor_account_id(token()).build());
+            contract.ft_on_transfer(account_id.clone(), U128(*amount), "".to_string());
+            testing_env!(context.predecessor_account_id(account_id.clone()).build());
+            contract.delegate(account_id.clone(), U128(*amount));
+        }
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .block_timestamp(env::block_timestamp() + 1)
+            .build());
+    }
+
+    // contract with an empty registry "testname" of alice, and its unique identifier
+    fn setup_registry() -> (VMContextBuilder, Contract, AccountId) {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        contract.new_registry(alice(), vec![], vec![], "testname".to_string());
+        let identifier = AccountId::from_str("testname.near").unwrap();
+        (context, contract, identifier)
+    }
+
+    #[test]
+    fn test_registry_proposal_kinds() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        let row = json!({"unique_identifier": 1, "data": "first"});
+
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::AddRows,
+            vec![row],
+        );
+        let row = json!({"unique_identifier": 1, "data": "second"});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::UpdateRows,
+            vec![row],
+        );
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(registry.get_row(1), Some(b"\"second\"".to_vec()));
+
+        let kind = ProposalKind::DeleteRows { row_ids: vec![1] };
+        let deleted = approve_proposal(&mut context, &mut contract, kind, vec![]);
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(registry.get_row(1), None);
+        assert!(contract.get_applied_change(deleted).is_some());
+
+        let kind = ProposalKind::RenameRegistry {
+            name: "renamed".to_string(),
+        };
+        approve_proposal(&mut context, &mut contract, kind, vec![]);
+        let kind = ProposalKind::TransferRegistryOwnership { new_owner: bob() };
+        approve_proposal(&mut context, &mut contract, kind, vec![]);
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(registry.name, "renamed");
+        assert_eq!(registry.owner, bob());
+        assert!(!contract.registries.contains_key(&alice()));
+
+        let deleted = approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::DeleteRegistry,
+            vec![],
+        );
+        assert_eq!(contract.get_proposal(deleted)[0]["status"], "Approved");
+        assert!(contract.internal_find_registry(&identifier).is_none());
+    }
+
+    #[test]
+    fn test_update_missing_row() {
         let context = get_context(alice());
         testing_env!(context.build());
-        // instantiate a contract variable with the counter at zero
-        let mut contract = Contract {
-            owner: alice(),
-            locked_amount: 0,
-            staking_id: None,
-            total_delegation_amount: 0,
-            delegations: LookupMap::new(b"d"),
-            last_proposal_id: 0,
-            proposals: TreeMap::new(b"p"),
-            registries: Default::default(),
-            dao: bob(),
-            voting_power: HashMap::new(),
+        let mut contract = Contract::init(alice(), bob());
+        contract.new_registry(alice(), vec![], vec![], "testname".to_string());
+        let mut input = proposal_input(ProposalKind::UpdateRows);
+        input.row = vec![json!({"unique_identifier": 1, "data": "first"})];
+        // `add_proposal` fails with this error.
+        assert_eq!(
+            contract.internal_validate_proposal(&input.into()),
+            Err("ERR_NO_ROW")
+        );
+    }
+
+    #[test]
+    fn test_transfer_payout() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        let kind = ProposalKind::Transfer {
+            token_id: token(),
+            receiver_id: alice(),
+            amount: U128(10),
+            msg: None,
         };
+        let id = approve_proposal(&mut context, &mut contract, kind, vec![]);
+        assert_eq!(contract.get_payout(id), Some(PayoutStatus::Pending));
+        assert_eq!(contract.cleanup_proposals(0, 10), Vec::<u64>::new());
 
-        let value = json!({"fruit": "Apple","size": "Large","color": "Red"});
-        contract.new_registry(
-            alice(),
-            vec![value.clone()],
-            vec![value.clone()],
-            "testname".to_string(),
+        set_callback_context(&mut context, PromiseResult::Failed);
+        contract.on_proposal_callback(id);
+        assert_eq!(contract.get_proposal(id)[0]["status"], "Failed");
+        assert_eq!(contract.get_payout(id), Some(PayoutStatus::Failed));
+
+        contract.retry_payout(id);
+        assert_eq!(contract.get_payout(id), Some(PayoutStatus::Pending));
+        set_callback_context(&mut context, PromiseResult::Successful(vec![]));
+        contract.on_proposal_callback(id);
+        assert_eq!(contract.get_proposal(id)[0]["status"], "Approved");
+        assert_eq!(contract.get_payout(id), None);
+    }
+
+    #[test]
+    fn test_bounty() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        let bounty = BountyInput {
+            unique_identifier: identifier.clone(),
+            description: "Add rows".to_string(),
+            amount: U128(10),
+            times: 2,
+        };
+        testing_env!(context.attached_deposit(20).build());
+        let near_bounty = contract.add_bounty(bounty.clone());
+        testing_env!(context.predecessor_account_id(token()).build());
+        contract.ft_on_transfer(alice(), U128(20), json!(bounty).to_string());
+        assert_eq!(contract.get_bounty(1).unwrap().token, Some(token()));
+
+        let row = json!({"unique_identifier": 1, "data": "first"});
+        let kind = ProposalKind::BountyDone {
+            bounty_id: near_bounty,
+        };
+        let id = approve_proposal(&mut context, &mut contract, kind, vec![row]);
+        assert_eq!(contract.get_proposal(id)[0]["status"], "Approved");
+        assert_eq!(contract.get_payout(id), Some(PayoutStatus::Pending));
+        assert_eq!(contract.get_bounty(near_bounty).unwrap().times, 1);
+
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.remove_bounty(near_bounty);
+        assert_eq!(contract.get_bounty(near_bounty).unwrap().times, 0);
+        assert_eq!(contract.reserved_amount, 10);
+        // A failed refund restores the unclaimed bounty.
+        set_callback_context(&mut context, PromiseResult::Failed);
+        assert!(!contract.on_remove_bounty_callback(near_bounty, 1));
+        assert_eq!(contract.get_bounty(near_bounty).unwrap().times, 1);
+        assert_eq!(contract.reserved_amount, 20);
+    }
+
+    #[test]
+    fn test_payout_keeps_reserved_funds() {
+        let mut context = get_context(alice());
+        testing_env!(context
+            .current_account_id(registry())
+            .account_balance(25 * ONE_NEAR + 1024 * 300 * env::storage_byte_cost())
+            .build());
+        let mut contract = Contract::init(alice(), bob());
+        contract.new_registry(alice(), vec![], vec![], "testname".to_string());
+        testing_env!(context.attached_deposit(20 * ONE_NEAR).build());
+        let bounty_id = contract.add_bounty(BountyInput {
+            unique_identifier: AccountId::from_str("testname.near").unwrap(),
+            description: "Add rows".to_string(),
+            amount: U128(10 * ONE_NEAR),
+            times: 2,
+        });
+        assert_eq!(contract.reserved_amount, 20 * ONE_NEAR);
+
+        // Funds held for the bounty can't pay for a transfer.
+        let kind = ProposalKind::Transfer {
+            token_id: AccountId::from_str(consts::OLD_BASE_TOKEN).unwrap(),
+            receiver_id: alice(),
+            amount: U128(10 * ONE_NEAR),
+            msg: None,
+        };
+        let transfer = approve_proposal(&mut context, &mut contract, kind, vec![]);
+        assert_eq!(contract.get_payout(transfer), Some(PayoutStatus::Failed));
+
+        let row = json!({"unique_identifier": 1, "data": "first"});
+        let kind = ProposalKind::BountyDone { bounty_id };
+        let claim = approve_proposal(&mut context, &mut contract, kind, vec![row]);
+        assert_eq!(contract.get_payout(claim), Some(PayoutStatus::Pending));
+        set_callback_context(&mut context, PromiseResult::Successful(vec![]));
+        contract.on_proposal_callback(claim);
+        assert_eq!(contract.reserved_amount, 10 * ONE_NEAR);
+    }
+
+    #[test]
+    fn test_contributor_attribution() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        let rows = vec![
+            json!({"unique_identifier": 1, "data": "first"}),
+            json!({"unique_identifier": 2, "data": "second"}),
+        ];
+        testing_env!(context.block_timestamp(5).build());
+        let id = approve_proposal(&mut context, &mut contract, ProposalKind::AddRows, rows);
+
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let rejected = added(contract.add_proposal(proposal_input(ProposalKind::Vote)));
+        set_callback_context(&mut context, dao_proposal_output(7, "Rejected"));
+        contract.proposal_result_callback(rejected);
+
+        let attribution = contract.get_row_attribution(identifier, 2).unwrap();
+        assert_eq!(attribution.proposal_id, id);
+        assert_eq!(attribution.proposer, bob());
+        assert_eq!(attribution.approved_at, U64(5));
+        assert_eq!(
+            contract.get_contributor(bob()),
+            ContributorStats {
+                proposals_accepted: 1,
+                proposals_rejected: 1,
+                rows_authored: 2,
+            }
         );
+    }
 
-        // let result = contract.get_registries();
-        // println!("{:?}", result);
+    #[test]
+    fn test_challenge_row() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        contract.set_registry_challenge_policy(
+            identifier.clone(),
+            Some(ChallengePolicy {
+                period: U64(100),
+                bond: U128(ONE_NEAR),
+                reward: U128(5),
+            }),
+        );
+        let rows = vec![
+            json!({"unique_identifier": 1, "data": "wrong"}),
+            json!({"unique_identifier": 2, "data": "right"}),
+        ];
+        approve_proposal(&mut context, &mut contract, ProposalKind::AddRows, rows);
 
-        // assert_eq!(value, result[1]);
+        let mut challenge = |contract: &mut Contract, row_id: u64, status: &str| {
+            testing_env!(context
+                .predecessor_account_id(alice())
+                .attached_deposit(2 * ONE_NEAR)
+                .block_timestamp(50)
+                .build());
+            let id = contract.challenge_row(identifier.clone(), row_id, "dispute".to_string());
+            assert!(contract
+                .row_disputes
+                .contains_key(&(identifier.clone(), row_id)));
+            set_callback_context(&mut context, dao_proposal_output(7, status));
+            contract.proposal_result_callback(id);
+            assert!(!contract
+                .row_disputes
+                .contains_key(&(identifier.clone(), row_id)));
+            id
+        };
+        let upheld = challenge(&mut contract, 1, "Approved");
+        let dismissed = challenge(&mut contract, 2, "Rejected");
 
-        let value2 = json!({"fruit": "Banana","size": "Large","color": "Yellow"});
-        contract.new_registry(
-            alice(),
-            vec![value2.clone()],
-            vec![value2.clone()],
-            "testnamenew".to_string(),
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(registry.get_row(1), None);
+        assert!(registry.get_row(2).is_some());
+        assert_eq!(contract.get_payout(upheld), Some(PayoutStatus::Pending));
+        assert_eq!(contract.get_proposal(dismissed)[0]["status"], "Rejected");
+        // The bond of the upheld challenge is held until it is paid out.
+        assert_eq!(contract.reserved_amount, ONE_NEAR);
+        set_callback_context(&mut context, PromiseResult::Successful(vec![]));
+        contract.on_proposal_callback(upheld);
+        assert_eq!(contract.reserved_amount, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PROPOSAL_OBJECTED")]
+    fn test_optimistic_approval() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        delegate_voting_power(&mut context, &mut contract, &[(bob(), 10)]);
+        set_callback_context(&mut context, dao_policy(ONE_NEAR, &["*:*"]));
+        contract.on_policy_callback();
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_optimistic_policy(
+            identifier.clone(),
+            Some(OptimisticPolicy {
+                period: U64(10),
+                veto_threshold: U128(ONE_NEAR),
+                reviewer_role: Some("all".to_string()),
+            }),
         );
 
-        let value3 = json!({"fruit": "Cherry","size": "Small","color": "Red"});
-        contract.new_registry(
-            alice(),
-            vec![value3.clone()],
-            vec![value3.clone()],
-            "testnamenew1".to_string(),
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let approved = added(contract.add_proposal(proposal_input(ProposalKind::Vote)));
+        let objected = added(contract.add_proposal(proposal_input(ProposalKind::Vote)));
+        // Optimistic proposals are not mirrored in the DAO.
+        assert!(near_sdk::test_utils::get_created_receipts().is_empty());
+        // Changing the policy doesn't affect proposals already submitted.
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_optimistic_policy(identifier.clone(), None);
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.act_proposal(objected, Action::VoteReject, U128(0));
+
+        testing_env!(context.block_timestamp(11).build());
+        contract.finalize_optimistic(approved);
+        assert_eq!(contract.get_proposal(approved)[0]["status"], "Approved");
+        assert_eq!(contract.get_locked_bond(bob()).0, ONE_NEAR);
+        contract.finalize_optimistic(objected);
+    }
+
+    #[test]
+    fn test_commit_reveal_voting() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        contract.set_staking_contract(token());
+        contract.set_registry_commit_reveal_policy(
+            identifier.clone(),
+            Some(CommitRevealPolicy {
+                commit_period: U64(10),
+                reveal_period: U64(10),
+                quorum: U128(10),
+            }),
         );
+        testing_env!(context.predecessor_account_id(token()).build());
+        contract.ft_on_transfer(alice(), U128(10), "".to_string());
+        contract.ft_on_transfer(bob(), U128(5), "".to_string());
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.delegate(alice(), U128(10));
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.delegate(bob(), U128(5));
 
-        let _result = contract.get_registries();
+        testing_env!(context
+            .block_timestamp(1)
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let id = added(contract.add_proposal(proposal_input(ProposalKind::Vote)));
+        // Proposals keep the policy they were submitted with.
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .attached_deposit(0)
+            .build());
+        contract.set_registry_commit_reveal_policy(identifier, None);
+        let hash = |vote: &str, salt: &str| {
+            Base64VecU8(env::sha256(format!("{}:{}", vote, salt).as_bytes()))
+        };
+        testing_env!(context.attached_deposit(VOTE_COMMIT_DEPOSIT).build());
+        contract.commit_vote(id, hash("VoteApprove", "alice salt"));
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.commit_vote(id, hash("VoteReject", "bob salt"));
+        testing_env!(context.predecessor_account_id(token()).build());
+        contract.commit_vote(id, hash("VoteReject", "token salt"));
 
-        let new_value = json!({"fruit": "Pineapple","size": "Medium","color": "Yellow"});
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .attached_deposit(0)
+            .block_timestamp(11)
+            .build());
+        contract.reveal_vote(id, Action::VoteApprove, "alice salt".to_string());
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.reveal_vote(id, Action::VoteReject, "bob salt".to_string());
+        // Deposits are refunded on reveal only.
+        let commits = contract.vote_commits.get(&id).unwrap();
+        assert_eq!(commits[&bob()].deposit, 0);
+        assert_eq!(commits[&token()].deposit, VOTE_COMMIT_DEPOSIT);
+        assert!(contract.get_proposal_voters(id).is_empty());
 
-        contract.voting_change_registry(
-            AccountId::from_str("testname.near").unwrap(),
-            vec![new_value.clone()],
-            vec![new_value.clone()],
+        testing_env!(context.block_timestamp(21).build());
+        assert_eq!(contract.tally_votes(id), ProposalStatus::Approved);
+        let tally = contract.get_proposal_tally(id);
+        assert_eq!((tally.approve, tally.reject), (U128(10), U128(5)));
+        assert_eq!(contract.get_proposal_voters(id).len(), 2);
+    }
+
+    #[test]
+    fn test_withdraw_refunds_vote_commits() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        contract.set_registry_commit_reveal_policy(
+            identifier,
+            Some(CommitRevealPolicy {
+                commit_period: U64(10),
+                reveal_period: U64(10),
+                quorum: U128(10),
+            }),
         );
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let id = added(contract.add_proposal(proposal_input(ProposalKind::Vote)));
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .attached_deposit(VOTE_COMMIT_DEPOSIT)
+            .build());
+        contract.commit_vote(id, Base64VecU8(env::sha256(b"VoteApprove:salt")));
 
-        let new_result = contract.get_registries();
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(0)
+            .build());
+        contract.withdraw_proposal(id);
+        assert!(contract.vote_commits.get(&id).is_none());
+        let refunds: Vec<_> = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == alice())
+            .flat_map(|receipt| receipt.actions)
+            .collect();
+        assert!(matches!(
+            refunds[..],
+            [near_sdk::mock::VmAction::Transfer { deposit }] if deposit == VOTE_COMMIT_DEPOSIT
+        ));
+    }
+
+    #[test]
+    fn test_vote_strategies() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        contract.set_staking_contract(token());
+        testing_env!(context.predecessor_account_id(token()).build());
+        contract.ft_on_transfer(alice(), U128(100), "".to_string());
+        contract.ft_on_transfer(bob(), U128(16), "".to_string());
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.delegate(alice(), U128(100));
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.delegate(bob(), U128(16));
+
+        testing_env!(context.block_timestamp(1).build());
+        let linear = create_proposal(&mut context, &mut contract);
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_vote_strategy(identifier.clone(), VoteStrategy::Quadratic);
+        let quadratic = create_proposal(&mut context, &mut contract);
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_vote_strategy(
+            identifier.clone(),
+            VoteStrategy::Conviction { period: U64(10) },
+        );
+        let conviction = create_proposal(&mut context, &mut contract);
+        // Proposals keep the strategy they were submitted with.
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_vote_strategy(identifier, VoteStrategy::Linear);
+        for id in [linear, quadratic, conviction] {
+            testing_env!(context.predecessor_account_id(alice()).build());
+            contract.act_proposal(id, Action::VoteApprove, U128(0));
+            testing_env!(context.predecessor_account_id(bob()).build());
+            contract.act_proposal(id, Action::VoteReject, U128(9));
+        }
+        let tally = contract.get_proposal_tally(linear);
+        assert_eq!((tally.approve, tally.reject), (U128(100), U128(9)));
+        let tally = contract.get_proposal_tally(quadratic);
+        assert_eq!((tally.approve, tally.reject), (U128(10), U128(3)));
+
+        testing_env!(context.block_timestamp(6).build());
+        let tally = contract.get_proposal_tally(conviction);
+        assert_eq!((tally.approve, tally.reject), (U128(50), U128(4)));
+        testing_env!(context.block_timestamp(100).build());
+        let voters = contract.get_proposal_voters(conviction);
+        assert!(voters.iter().any(|voter| voter.weight == U128(100)));
+        assert!(voters.iter().any(|voter| voter.weight == U128(9)));
+    }
 
-        println!("{:?}", new_result);
+    #[test]
+    fn test_vote_strategy_decisions() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        contract.set_voting_period(Some(U64(10)));
+        delegate_voting_power(&mut context, &mut contract, &[(alice(), 100), (bob(), 16)]);
+        let mut ids = vec![];
+        for strategy in [
+            VoteStrategy::Linear,
+            VoteStrategy::Quadratic,
+            VoteStrategy::Conviction { period: U64(10) },
+        ] {
+            testing_env!(context.predecessor_account_id(alice()).build());
+            contract.set_registry_vote_strategy(identifier.clone(), strategy);
+            let id = create_proposal(&mut context, &mut contract);
+            testing_env!(context
+                .predecessor_account_id(alice())
+                .attached_deposit(0)
+                .build());
+            contract.act_proposal(id, Action::VoteApprove, U128(0));
+            testing_env!(context.predecessor_account_id(bob()).build());
+            contract.act_proposal(id, Action::VoteReject, U128(0));
+            ids.push(id);
+        }
+        let decision = |contract: &Contract, id: u64| {
+            let proposal: Proposal = contract.proposals.get(&id).unwrap().into();
+            contract.internal_local_decision(id, &proposal)
+        };
+        // Linear votes are decided once the remaining stake can't change the outcome,
+        // non-linear ones at the end of the voting period.
+        assert_eq!(decision(&contract, ids[0]), Some(Action::VoteApprove));
+        assert_eq!(decision(&contract, ids[1]), None);
+        assert_eq!(decision(&contract, ids[2]), None);
+        contract.relay_votes(ids[0]);
+
+        testing_env!(context.block_timestamp(env::block_timestamp() + 10).build());
+        for &id in &ids[1..] {
+            assert_eq!(decision(&contract, id), Some(Action::VoteApprove));
+            contract.relay_votes(id);
+            set_callback_context(
+                &mut context,
+                PromiseResult::Successful(json!(true).to_string().into_bytes()),
+            );
+            assert!(contract.on_relay_votes_callback(id));
+            let proposal: Proposal = contract.proposals.get(&id).unwrap().into();
+            assert_eq!(proposal.status, ProposalStatus::InProgress);
+            assert_eq!(proposal.relayed_vote, Some(Action::VoteApprove));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_GATED")]
+    fn test_challenge_row_token_gated() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        contract.set_registry_challenge_policy(
+            identifier.clone(),
+            Some(ChallengePolicy {
+                period: U64(100),
+                bond: U128(ONE_NEAR),
+                reward: U128(5),
+            }),
+        );
+        let rows = vec![json!({"unique_identifier": 1, "data": "wrong"})];
+        approve_proposal(&mut context, &mut contract, ProposalKind::AddRows, rows);
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_token_gate(
+            identifier.clone(),
+            Some(TokenGate::FungibleToken {
+                token_id: token(),
+                min_balance: U128(10),
+            }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(2 * ONE_NEAR)
+            .block_timestamp(50)
+            .build());
+        contract.challenge_row(identifier, 1, "dispute".to_string());
+    }
+
+    #[test]
+    fn test_token_gate() {
+        let (mut context, mut contract, unique_identifier) = setup_registry();
+        delegate_voting_power(&mut context, &mut contract, &[(bob(), 10)]);
+        contract.set_registry_token_gate(
+            unique_identifier.clone(),
+            Some(TokenGate::FungibleToken {
+                token_id: token(),
+                min_balance: U128(10),
+            }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        assert!(matches!(
+            contract.add_proposal(proposal_input(ProposalKind::Vote)),
+            PromiseOrValue::Promise(_)
+        ));
+        let balance =
+            |balance: &str| PromiseResult::Successful(json!(balance).to_string().into_bytes());
+        set_callback_context(&mut context, balance("9"));
+        let gated = contract.on_token_gate_proposal_callback(
+            bob(),
+            proposal_input(ProposalKind::Vote),
+            U128(ONE_NEAR),
+        );
+        assert_eq!(gated, None);
+        assert_eq!(contract.get_last_proposal_id(), 0);
+        set_callback_context(&mut context, balance("10"));
+        // Proposals that can no longer be added refund the bond instead of panicking.
+        let gated = contract.on_token_gate_proposal_callback(
+            bob(),
+            proposal_input(ProposalKind::Vote),
+            U128(ONE_NEAR - 1),
+        );
+        assert_eq!(gated, None);
+        assert_eq!(contract.get_last_proposal_id(), 0);
+        let id = contract
+            .on_token_gate_proposal_callback(
+                bob(),
+                proposal_input(ProposalKind::Vote),
+                U128(ONE_NEAR),
+            )
+            .unwrap();
+        assert_eq!(contract.get_proposal(id)[0]["proposer"], json!(bob()));
+        set_callback_context(
+            &mut context,
+            PromiseResult::Successful(json!(7).to_string().into_bytes()),
+        );
+        contract.callback_add_proposal_result(id);
+
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_token_gate(
+            unique_identifier,
+            Some(TokenGate::NonFungibleToken {
+                contract_id: token(),
+            }),
+        );
+        set_callback_context(
+            &mut context,
+            PromiseResult::Successful(json!([{ "token_id": "1" }]).to_string().into_bytes()),
+        );
+        contract.on_token_gate_vote_callback(id, bob(), Action::VoteApprove, U128(0));
+        let voters = contract.get_proposal_voters(id);
+        assert_eq!(voters.len(), 1);
+        assert_eq!(voters[0].account_id, bob());
+    }
+
+    #[test]
+    fn test_row_receipts() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        contract.set_registry_receipt_policy(
+            identifier.clone(),
+            Some(ReceiptPolicy {
+                nft_contract_id: token(),
+                receiver_id: bob(),
+                mint_deposit: U128(0),
+            }),
+        );
+        let receipt_calls = || -> Vec<(String, Value)> {
+            near_sdk::test_utils::get_created_receipts()
+                .into_iter()
+                .filter(|receipt| receipt.receiver_id == token())
+                .flat_map(|receipt| receipt.actions)
+                .filter_map(|action| match action {
+                    near_sdk::mock::VmAction::FunctionCall {
+                        function_name,
+                        args,
+                        ..
+                    } => Some((function_name, serde_json::from_slice(&args).unwrap())),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let process = |context: &mut VMContextBuilder, contract: &mut Contract| {
+            testing_env!(context.attached_deposit(0).build());
+            contract.process_receipts(10);
+            receipt_calls()
+        };
+
+        let row = json!({"unique_identifier": 1, "data": "first"});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::AddRows,
+            vec![row],
+        );
+        assert!(receipt_calls().is_empty());
+        assert_eq!(contract.get_pending_receipts(10).len(), 1);
+        let calls = process(&mut context, &mut contract);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "nft_mint");
+        assert_eq!(calls[0].1["token_id"], "testname.near:1");
+        assert_eq!(calls[0].1["receiver_id"], json!(bob()));
+        let extra: Value =
+            serde_json::from_str(calls[0].1["token_metadata"]["extra"].as_str().unwrap()).unwrap();
+        assert_eq!(extra, json!({"registry": "testname.near", "row_id": 1}));
+        assert!(contract.get_pending_receipts(10).is_empty());
+
+        let row = json!({"unique_identifier": 1, "data": "second"});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::UpdateRows,
+            vec![row],
+        );
+        assert!(process(&mut context, &mut contract).is_empty());
+
+        let kind = ProposalKind::DeleteRows { row_ids: vec![1] };
+        approve_proposal(&mut context, &mut contract, kind, vec![]);
+        let calls = process(&mut context, &mut contract);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "nft_burn");
+        assert_eq!(calls[0].1, json!({"token_id": "testname.near:1"}));
+
+        // Mints wait in the queue while the balance doesn't cover their deposit.
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_receipt_policy(
+            identifier,
+            Some(ReceiptPolicy {
+                nft_contract_id: token(),
+                receiver_id: bob(),
+                mint_deposit: U128(env::account_balance() + 1),
+            }),
+        );
+        let row = json!({"unique_identifier": 2, "data": "third"});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::AddRows,
+            vec![row],
+        );
+        assert!(process(&mut context, &mut contract).is_empty());
+        assert_eq!(contract.get_pending_receipts(10).len(), 1);
+    }
+
+    #[test]
+    fn test_read_api() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        let rows = vec![
+            json!({"unique_identifier": 1, "data": {"account_id": "bob.near", "score": 5}}),
+            json!({"unique_identifier": 2, "data": "alice.near"}),
+        ];
+        approve_proposal(&mut context, &mut contract, ProposalKind::AddRows, rows);
+
+        assert!(contract.is_member(identifier.clone(), alice()));
+        assert!(!contract.is_member(identifier.clone(), bob()));
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_key_field(identifier.clone(), Some("account_id".to_string()));
+        assert!(contract.is_member(identifier.clone(), bob()));
+        assert!(!contract.is_member(identifier.clone(), alice()));
+        assert_eq!(
+            contract.get_row_by_key(identifier.clone(), "bob.near".to_string()),
+            Some(RowOutput {
+                unique_identifier: 1,
+                data: json!({"account_id": "bob.near", "score": 5}),
+            })
+        );
+        assert_eq!(
+            contract.get_field(identifier.clone(), 1, "score".to_string()),
+            Some(json!(5))
+        );
+        assert_eq!(
+            contract.get_field(identifier.clone(), 2, "score".to_string()),
+            None
+        );
+
+        // Keys stay unique while a key field is set.
+        let mut input = proposal_input(ProposalKind::AddRows);
+        input.row = vec![json!({"unique_identifier": 3, "data": {"account_id": "bob.near"}})];
+        assert_eq!(
+            contract.internal_validate_proposal(&input.into()),
+            Err("ERR_DUPLICATE_KEY")
+        );
+        let row = json!({"unique_identifier": 1, "data": {"account_id": "carol.near"}});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::UpdateRows,
+            vec![row],
+        );
+        assert_eq!(
+            contract.get_row_by_key(identifier.clone(), "carol.near".to_string()),
+            Some(RowOutput {
+                unique_identifier: 1,
+                data: json!({"account_id": "carol.near"}),
+            })
+        );
+        assert_eq!(
+            contract.get_row_by_key(identifier.clone(), "bob.near".to_string()),
+            None
+        );
+        // The freed key can be taken by another row.
+        let row = json!({"unique_identifier": 3, "data": {"account_id": "bob.near"}});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::AddRows,
+            vec![row],
+        );
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(
+            registry.build_key_index(Some(&"account_id".to_string())),
+            Ok(registry.key_index.clone())
+        );
+        assert_eq!(
+            registry.key_index,
+            HashMap::from([
+                ("carol.near".to_string(), vec![1]),
+                ("bob.near".to_string(), vec![3]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        testing_env!(context
+            .predecessor_account_id(token())
+            .attached_deposit(SUBSCRIPTION_DEPOSIT)
+            .build());
+        contract.subscribe(identifier.clone(), U64(10_000_000_000_000));
+        let notifications = || -> Vec<Value> {
+            near_sdk::test_utils::get_created_receipts()
+                .into_iter()
+                .filter(|receipt| receipt.receiver_id == token())
+                .flat_map(|receipt| receipt.actions)
+                .filter_map(|action| match action {
+                    near_sdk::mock::VmAction::FunctionCall {
+                        function_name,
+                        args,
+                        ..
+                    } if function_name == "on_registry_change" => {
+                        Some(serde_json::from_slice(&args).unwrap())
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let row = json!({"unique_identifier": 1, "data": "first"});
+        let id = approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::AddRows,
+            vec![row],
+        );
+        assert_eq!(
+            notifications(),
+            vec![json!({
+                "registry_id": "testname.near",
+                "version": 1,
+                "change_summary": {
+                    "proposal_id": id,
+                    "kind": "add_rows",
+                    "rows": [1],
+                    "columns": [],
+                },
+            })]
+        );
+
+        // Notifications that don't fit in the gas left are kept for a retry.
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let mut input = proposal_input(ProposalKind::UpdateRows);
+        input.row = vec![json!({"unique_identifier": 1, "data": "second"})];
+        let id = added(contract.add_proposal(input));
+        set_callback_context(
+            context.prepaid_gas(near_sdk::Gas(15_000_000_000_000)),
+            dao_proposal_output(7, "Approved"),
+        );
+        contract.proposal_result_callback(id);
+        assert!(notifications().is_empty());
+        assert_eq!(
+            contract.get_missed_notifications(identifier.clone()).len(),
+            1
+        );
+        testing_env!(context
+            .prepaid_gas(near_sdk::Gas(300_000_000_000_000))
+            .attached_deposit(0)
+            .build());
+        assert_eq!(contract.retry_notifications(identifier.clone(), 10), 1);
+        assert_eq!(notifications()[0]["version"], json!(2));
+        assert!(contract
+            .get_missed_notifications(identifier.clone())
+            .is_empty());
+
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.unsubscribe(identifier, token());
+        let refunds: Vec<_> = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == token())
+            .flat_map(|receipt| receipt.actions)
+            .collect();
+        assert!(matches!(
+            refunds[..],
+            [near_sdk::mock::VmAction::Transfer { deposit }] if deposit == SUBSCRIPTION_DEPOSIT
+        ));
+        let kind = ProposalKind::DeleteRows { row_ids: vec![1] };
+        approve_proposal(&mut context, &mut contract, kind, vec![]);
+        assert!(notifications().is_empty());
+    }
+
+    #[test]
+    fn test_merkle_proofs() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        assert_eq!(contract.get_merkle_root(identifier.clone()), None);
+        let rows = (1..=5)
+            .map(|id| json!({"unique_identifier": id, "data": id}))
+            .collect();
+        approve_proposal(&mut context, &mut contract, ProposalKind::AddRows, rows);
+        let row = json!({"unique_identifier": 3, "data": "updated"});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::UpdateRows,
+            vec![row],
+        );
+        let kind = ProposalKind::DeleteRows { row_ids: vec![2] };
+        approve_proposal(&mut context, &mut contract, kind, vec![]);
+
+        let root = contract.get_merkle_root(identifier.clone()).unwrap();
+        for row_id in [1, 3, 4, 5] {
+            let proof = contract
+                .get_merkle_proof(identifier.clone(), row_id)
+                .unwrap();
+            assert_eq!(proof.root, root);
+            assert!(merkle::verify_proof(
+                &root.0,
+                row_id,
+                proof.data.as_bytes(),
+                &proof.proof
+            ));
+            assert!(!merkle::verify_proof(
+                &root.0,
+                row_id,
+                b"forged",
+                &proof.proof
+            ));
+        }
+        let proof = contract.get_merkle_proof(identifier.clone(), 3).unwrap();
+        assert_eq!(proof.data, "\"updated\"");
+        assert!(contract.get_merkle_proof(identifier, 2).is_none());
+    }
+
+    #[test]
+    fn test_row_content_hashes() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        let row = json!({"unique_identifier": 1, "data": {"b": 1, "a": 2}});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::AddRows,
+            vec![row],
+        );
+        let hash = contract.get_row_hash(identifier.clone(), 1).unwrap();
+        assert_eq!(hash.0, env::sha256(br#"{"a":2,"b":1}"#));
+
+        // Both updates expect the original row, only the first one applies.
+        let updates: Vec<u64> = ["second", "third"]
+            .iter()
+            .map(|data| {
+                testing_env!(context
+                    .predecessor_account_id(bob())
+                    .attached_deposit(ONE_NEAR)
+                    .build());
+                let mut input = proposal_input(ProposalKind::UpdateRows);
+                input.row = vec![json!({"unique_identifier": 1, "data": data})];
+                input.expected_hashes = HashMap::from([(1, hash.clone())]);
+                added(contract.add_proposal(input))
+            })
+            .collect();
+        for id in &updates {
+            set_callback_context(&mut context, dao_proposal_output(7, "Approved"));
+            contract.proposal_result_callback(*id);
+        }
+        assert_eq!(contract.get_proposal(updates[0])[0]["status"], "Approved");
+        assert_eq!(contract.get_proposal(updates[1])[0]["status"], "Failed");
+        assert_eq!(
+            contract.get_row_hash(identifier, 1).unwrap().0,
+            env::sha256(b"\"second\"")
+        );
+    }
+
+    #[test]
+    fn test_canonical_row_data() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        let data =
+            serde_json::from_str::<Value>(r#"{"z": 1, "y": {"b": 2.0, "a": -0.0}}"#).unwrap();
+        contract.new_registry(alice(), vec![], vec![data], "testname".to_string());
+        let identifier = AccountId::from_str("testname.near").unwrap();
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(
+            registry.row[0].data,
+            br#"{"y":{"a":0,"b":2},"z":1}"#.to_vec()
+        );
+
+        let data = serde_json::from_str::<Value>(r#"{"b": [1.50, 3.0], "a": "x"}"#).unwrap();
+        let row = json!({"unique_identifier": 1, "data": data});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::AddRows,
+            vec![row],
+        );
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(
+            registry.get_row(1),
+            Some(br#"{"a":"x","b":[1.5,3]}"#.to_vec())
+        );
+    }
+
+    #[test]
+    fn test_row_schema_encoding() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        let data = json!({"name": "apple", "count": 3, "price": 1.5, "ripe": true});
+        contract.new_registry(alice(), vec![], vec![data.clone()], "testname".to_string());
+        let identifier = AccountId::from_str("testname.near").unwrap();
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        let (row_id, json) = (
+            registry.row[0].unique_identifier,
+            registry.row[0].data.clone(),
+        );
+        let hash = contract.get_row_hash(identifier.clone(), row_id);
+        let root = contract.get_merkle_root(identifier.clone());
+
+        let schema = vec![
+            Field {
+                name: "name".to_string(),
+                kind: FieldKind::String,
+            },
+            Field {
+                name: "count".to_string(),
+                kind: FieldKind::U64,
+            },
+            Field {
+                name: "price".to_string(),
+                kind: FieldKind::F64,
+            },
+            Field {
+                name: "ripe".to_string(),
+                kind: FieldKind::Bool,
+            },
+        ];
+        contract.set_registry_schema(identifier.clone(), Some(schema));
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(registry.row[0].data[0], 0);
+        assert!(registry.row[0].data.len() < json.len());
+        assert_eq!(registry.get_row(row_id), Some(json.clone()));
+        assert_eq!(contract.get_row_hash(identifier.clone(), row_id), hash);
+        assert_eq!(contract.get_merkle_root(identifier.clone()), root);
+        assert_eq!(
+            contract.get_field(identifier.clone(), row_id, "count".to_string()),
+            Some(json!(3))
+        );
+
+        let rows = vec![
+            json!({"unique_identifier": 1001, "data": {"name": "pear", "ripe": false}}),
+            json!({"unique_identifier": 1002, "data": {"name": "plum", "color": "blue"}}),
+        ];
+        approve_proposal(&mut context, &mut contract, ProposalKind::AddRows, rows);
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(registry.row[1].data[0], 0);
+        assert_eq!(
+            registry.get_row(1001),
+            Some(br#"{"name":"pear","ripe":false}"#.to_vec())
+        );
+        assert_eq!(
+            registry.row[2].data,
+            br#"{"color":"blue","name":"plum"}"#.to_vec()
+        );
+
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.set_registry_schema(identifier.clone(), None);
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(registry.row[0].data, json);
+        assert_eq!(
+            registry.row[1].data,
+            br#"{"name":"pear","ripe":false}"#.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_batched_schema_reencoding() {
+        let (mut context, mut contract, identifier) = setup_registry();
+        let rows = (1..=60)
+            .map(|id| {
+                json!({"unique_identifier": id, "data": {"name": format!("row{}", id), "count": id}})
+            })
+            .collect();
+        approve_proposal(&mut context, &mut contract, ProposalKind::AddRows, rows);
+        let field = |name: &str, kind: FieldKind| Field {
+            name: name.to_string(),
+            kind,
+        };
+        let count = |contract: &Contract, id: u64| {
+            contract.get_field(identifier.clone(), id, "count".to_string())
+        };
+
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .attached_deposit(0)
+            .build());
+        let schema = vec![
+            field("name", FieldKind::String),
+            field("count", FieldKind::U64),
+        ];
+        contract.set_registry_schema(identifier.clone(), Some(schema));
+        assert_eq!(contract.reencode_rows(identifier.clone(), 5), 5);
+        assert_eq!(contract.reencode_rows(identifier.clone(), 100), 0);
+
+        // Rows left by a schema change are still read with the previous schema.
+        let schema = vec![
+            field("count", FieldKind::U64),
+            field("name", FieldKind::String),
+        ];
+        contract.set_registry_schema(identifier.clone(), Some(schema));
+        let pending = |contract: &Contract| {
+            let registry = contract.internal_find_registry(&identifier).unwrap();
+            registry
+                .schema_migration
+                .as_ref()
+                .map_or(0, |migration| migration.pending.len())
+        };
+        assert_eq!(pending(&contract), 10);
+        assert!((1..=60).all(|id| count(&contract, id) == Some(json!(id))));
+
+        // Rows changed meanwhile are stored by the new schema.
+        let row = json!({"unique_identifier": 60, "data": {"name": "updated", "count": 60}});
+        approve_proposal(
+            &mut context,
+            &mut contract,
+            ProposalKind::UpdateRows,
+            vec![row],
+        );
+        assert_eq!(pending(&contract), 9);
+        assert_eq!(contract.reencode_rows(identifier.clone(), 100), 0);
+        assert_eq!(pending(&contract), 0);
+        assert!((1..=60).all(|id| count(&contract, id) == Some(json!(id))));
+    }
+
+    #[test]
+    fn test_migrate_from_v1() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let identifier = AccountId::from_str("testname.near").unwrap();
+        let mut proposals = TreeMap::new(b"p".to_vec());
+        proposals.insert(
+            &0,
+            &VersionedProposal::Default(ProposalV1 {
+                proposer: bob(),
+                description: "test".to_string(),
+                kind: ProposalKind::Vote,
+                status: ProposalStatus::InProgress,
+                vote_counts: HashMap::new(),
+                votes: HashMap::from([(alice(), Vote::InProgress)]),
+                submission_time: U64(0),
+                new_registries: HashMap::new(),
+                new_columns: vec![],
+                new_rows: vec![RowV1 {
+                    unique_identifier: 2,
+                    data: br#"{"b": 1, "a": 2}"#.to_vec(),
+                }],
+                unique_identifier: identifier.clone(),
+            }),
+        );
+        // Shape left by the first version's `init`, with a stray locked amount.
+        let old = ContractV1 {
+            owner: alice(),
+            locked_amount: 10,
+            staking_id: Some(alice()),
+            total_delegation_amount: 0,
+            delegations: LookupMap::new(b"d".to_vec()),
+            last_proposal_id: 1,
+            proposals,
+            registries: HashMap::from([(
+                alice(),
+                vec![RegistryDataV1 {
+                    dao: bob().to_string(),
+                    name: "testname".to_string(),
+                    owner: alice(),
+                    unique_identifier: identifier.clone(),
+                    row: vec![RowV1 {
+                        unique_identifier: 1,
+                        data: br#"{"b": 1, "a": 2}"#.to_vec(),
+                    }],
+                    column: vec![],
+                }],
+            )]),
+            dao: bob(),
+            voting_power: HashMap::from([(0, vec![(alice(), 5, Action::VoteApprove)])]),
+        };
+        env::state_write(&old);
+
+        let contract = Contract::migrate();
+        let registry = contract.internal_find_registry(&identifier).unwrap();
+        assert_eq!(registry.get_row(1), Some(br#"{"a":2,"b":1}"#.to_vec()));
+        assert!(contract.get_merkle_root(identifier.clone()).is_some());
+        assert_eq!(contract.get_last_proposal_id(), 1);
+        let proposal: Proposal = contract.proposals.get(&0).unwrap().into();
+        assert_eq!(proposal.new_rows[0].data, br#"{"a":2,"b":1}"#.to_vec());
+        assert_eq!(proposal.vote_strategy, VoteStrategy::Linear);
+        assert_eq!(contract.get_proposal_voters(0)[0].weight, U128(5));
+        assert_eq!(contract.locked_amount, 0);
+        assert!(contract.staking_id.is_none());
+
+        // Migrating again keeps the current state.
+        env::state_write(&contract);
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_last_proposal_id(), 1);
+        assert!(contract.internal_find_registry(&identifier).is_some());
+
+        // Staking can be set up and delegated to.
+        delegate_voting_power(&mut context, &mut contract, &[(bob(), 10)]);
+        assert_eq!(contract.get_total_delegation_amount(), U128(10));
+
+        // The legacy proposal can't be decided by the DAO, but can be expired and archived.
+        contract.expire_legacy_proposal(0);
+        let proposal: Proposal = contract.proposals.get(&0).unwrap().into();
+        assert_eq!(proposal.status, ProposalStatus::Expired);
+        assert_eq!(contract.cleanup_proposals(0, 10), vec![0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_LEGACY_PROPOSAL")]
+    fn test_expire_new_proposal_as_legacy() {
+        let mut context = get_context(alice());
+        testing_env!(context.current_account_id(registry()).build());
+        let mut contract = Contract::init(alice(), bob());
+        let id = create_proposal(&mut context, &mut contract);
+        contract.expire_legacy_proposal(id);
     }
 
     // #[test]
@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use crate::types::RowOutput;
+use crate::*;
+
+impl RegistryData {
+    /// Key of a row: its `key_field` if set, otherwise its data if that is a string.
+    fn row_key(key_field: Option<&String>, data: &Value) -> Option<String> {
+        let key = match key_field {
+            Some(field) => data.get(field)?,
+            None => data,
+        };
+        key.as_str().map(|key| key.to_string())
+    }
+
+    /// Key of a row given as canonical JSON.
+    fn row_json_key(&self, json: &[u8]) -> Option<String> {
+        let data: Value = serde_json::from_slice(json).ok()?;
+        Self::row_key(self.key_field.as_ref(), &data)
+    }
+
+    /// Decoded data of given row.
+    pub(crate) fn get_row_value(&self, unique_identifier: u64) -> Option<Value> {
+        self.row
+            .iter()
+            .find(|row| row.unique_identifier == unique_identifier)
+            .map(|row| self.row_value(row))
+    }
+
+    /// First row with given key.
+    pub(crate) fn find_row_by_key(&self, key: &str) -> Option<RowOutput> {
+        let unique_identifier = *self.key_index.get(key)?.first()?;
+        Some(RowOutput {
+            unique_identifier,
+            data: self.get_row_value(unique_identifier)?,
+        })
+    }
+
+    /// Ids of rows per key for given key field, in row order.
+    /// Keys must be unique if a key field is given.
+    pub(crate) fn build_key_index(
+        &self,
+        key_field: Option<&String>,
+    ) -> Result<HashMap<String, Vec<u64>>, &'static str> {
+        let mut index: HashMap<String, Vec<u64>> = HashMap::new();
+        for row in &self.row {
+            if let Some(key) = Self::row_key(key_field, &self.row_value(row)) {
+                let ids = index.entry(key).or_default();
+                if key_field.is_some() && !ids.is_empty() {
+                    return Err("ERR_DUPLICATE_KEY");
+                }
+                ids.push(row.unique_identifier);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Moves given row in the key index from the key of its previous data to that of its data.
+    pub(crate) fn update_key_index(
+        &mut self,
+        unique_identifier: u64,
+        previous: Option<&[u8]>,
+        json: Option<&[u8]>,
+    ) {
+        let previous = previous.and_then(|previous| self.row_json_key(previous));
+        let key = json.and_then(|json| self.row_json_key(json));
+        if previous == key {
+            return;
+        }
+        if let Some(previous) = previous {
+            if let Some(ids) = self.key_index.get_mut(&previous) {
+                ids.retain(|id| *id != unique_identifier);
+                if ids.is_empty() {
+                    self.key_index.remove(&previous);
+                }
+            }
+        }
+        if let Some(key) = key {
+            self.key_index
+                .entry(key)
+                .or_default()
+                .push(unique_identifier);
+        }
+    }
+
+    /// Checks that given new or updated rows don't take the key of another row,
+    /// if the registry has a key field.
+    pub(crate) fn check_row_keys(&self, rows: &[Row]) -> Result<(), &'static str> {
+        if self.key_field.is_none() {
+            return Ok(());
+        }
+        let mut keys = HashSet::new();
+        for row in rows {
+            let key = match self.row_json_key(&row.data) {
+                Some(key) => key,
+                None => continue,
+            };
+            let taken = self
+                .key_index
+                .get(&key)
+                .is_some_and(|ids| ids.iter().any(|id| *id != row.unique_identifier));
+            if taken || !keys.insert(key) {
+                return Err("ERR_DUPLICATE_KEY");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the field of row data that keys rows of given registry for `get_row_by_key`
+    /// and `is_member`. `None` keys rows by their data if it is a string.
+    /// Fails if rows share a value of the key field. Only for the registry owner.
+    pub fn set_registry_key_field(
+        &mut self,
+        unique_identifier: AccountId,
+        key_field: Option<String>,
+    ) {
+        let registry = self.internal_owned_registry_mut(&unique_identifier);
+        match registry.build_key_index(key_field.as_ref()) {
+            Ok(key_index) => {
+                registry.key_index = key_index;
+                registry.key_field = key_field;
+            }
+            Err(err) => env::panic_str(err),
+        }
+    }
+}
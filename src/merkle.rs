@@ -0,0 +1,149 @@
+//! Merkle commitment over rows of a registry.
+//!
+//! Leaves are `sha256(0x00 || row_id as u64 LE || data)` in the order rows were added,
+//! inner nodes are `sha256(0x01 || left || right)`. A node without a right sibling is
+//! carried up as is. Hashing doesn't depend on the NEAR runtime, so proofs can be checked
+//! with `verify_proof` anywhere.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+/// Sibling on the path from a leaf to the root.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProofStep {
+    pub hash: Base64VecU8,
+    /// Whether the sibling is on the left.
+    pub left: bool,
+}
+
+/// Hash of the leaf for given row.
+pub fn leaf_hash(row_id: u64, data: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(row_id.to_le_bytes());
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Checks that the row with given data is included in the tree with given root.
+pub fn verify_proof(root: &[u8], row_id: u64, data: &[u8], proof: &[ProofStep]) -> bool {
+    let mut hash = leaf_hash(row_id, data);
+    for step in proof {
+        let sibling: Hash = match step.hash.0.as_slice().try_into() {
+            Ok(sibling) => sibling,
+            Err(_) => return false,
+        };
+        hash = if step.left {
+            node_hash(&sibling, &hash)
+        } else {
+            node_hash(&hash, &sibling)
+        };
+    }
+    hash.as_slice() == root
+}
+
+/// Merkle tree over rows of a registry, updated along a single path on every change.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct MerkleTree {
+    /// Row of each leaf.
+    row_ids: Vec<u64>,
+    /// Leaves first, root last.
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn root(&self) -> Option<Hash> {
+        self.levels.last().and_then(|level| level.first()).copied()
+    }
+
+    /// Adds leaf for a new row.
+    pub(crate) fn push(&mut self, row_id: u64, data: &[u8]) {
+        if self.levels.is_empty() {
+            self.levels.push(vec![]);
+        }
+        self.row_ids.push(row_id);
+        self.levels[0].push(leaf_hash(row_id, data));
+        self.update_path(self.row_ids.len() - 1);
+    }
+
+    /// Sets leaf of given row, removing it if `None`.
+    /// A removed leaf is replaced by the last one.
+    pub(crate) fn set(&mut self, row_id: u64, data: Option<&[u8]>) {
+        let index = self.row_ids.iter().position(|id| *id == row_id);
+        match (index, data) {
+            (Some(index), Some(data)) => {
+                self.levels[0][index] = leaf_hash(row_id, data);
+                self.update_path(index);
+            }
+            (Some(index), None) => {
+                self.row_ids.swap_remove(index);
+                self.levels[0].swap_remove(index);
+                if index < self.row_ids.len() {
+                    self.update_path(index);
+                }
+                self.update_path(self.row_ids.len().saturating_sub(1));
+            }
+            (None, Some(data)) => self.push(row_id, data),
+            (None, None) => {}
+        }
+    }
+
+    /// Path from the leaf of given row to the root.
+    pub fn proof(&self, row_id: u64) -> Option<Vec<ProofStep>> {
+        let mut index = self.row_ids.iter().position(|id| *id == row_id)?;
+        let mut proof = vec![];
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if let Some(hash) = level.get(sibling) {
+                proof.push(ProofStep {
+                    hash: Base64VecU8(hash.to_vec()),
+                    left: sibling < index,
+                });
+            }
+            index /= 2;
+        }
+        Some(proof)
+    }
+
+    /// Recomputes ancestors of given leaf, fitting each level to the one below.
+    fn update_path(&mut self, mut index: usize) {
+        let mut level = 0;
+        while self.levels[level].len() > 1 {
+            let parent = index / 2;
+            let children = &self.levels[level];
+            let len = children.len().div_ceil(2);
+            let hash = match children.get(parent * 2 + 1) {
+                Some(right) => node_hash(&children[parent * 2], right),
+                None => children[parent * 2],
+            };
+            if self.levels.len() == level + 1 {
+                self.levels.push(vec![]);
+            }
+            let parents = &mut self.levels[level + 1];
+            if parent < parents.len() {
+                parents[parent] = hash;
+            } else {
+                parents.push(hash);
+            }
+            parents.truncate(len);
+            index = parent;
+            level += 1;
+        }
+        self.levels.truncate(level + 1);
+    }
+}
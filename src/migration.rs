@@ -0,0 +1,161 @@
+use crate::canonical::{canonical_json, content_hash};
+use crate::merkle::MerkleTree;
+use crate::*;
+
+/// Row as stored by the first version of the contract.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug)]
+pub struct RowV1 {
+    pub(crate) unique_identifier: u64,
+    #[serde(skip_serializing)]
+    pub(crate) data: Vec<u8>,
+}
+
+impl From<RowV1> for Row {
+    fn from(row: RowV1) -> Self {
+        let data = serde_json::from_slice::<Value>(&row.data)
+            .map(|value| canonical_json(&value))
+            .unwrap_or(row.data);
+        Self {
+            unique_identifier: row.unique_identifier,
+            content_hash: content_hash(&data),
+            data,
+            attribution: None,
+        }
+    }
+}
+
+/// Registry as stored by the first version of the contract.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(crate) struct RegistryDataV1 {
+    pub(crate) dao: String,
+    pub(crate) name: String,
+    pub(crate) owner: AccountId,
+    pub(crate) unique_identifier: AccountId,
+    pub(crate) row: Vec<RowV1>,
+    pub(crate) column: Vec<Column>,
+}
+
+impl From<RegistryDataV1> for RegistryData {
+    fn from(old: RegistryDataV1) -> Self {
+        let mut registry =
+            RegistryData::new(old.name, vec![], vec![], old.owner, old.dao, vec![], vec![]);
+        registry.unique_identifier = old.unique_identifier;
+        registry.row = old.row.into_iter().map(Row::from).collect();
+        registry.column = old.column;
+        let mut merkle = MerkleTree::default();
+        for row in &registry.row {
+            merkle.push(row.unique_identifier, &row.data);
+        }
+        registry.merkle = merkle;
+        registry.key_index = registry.build_key_index(None).unwrap_or_default();
+        registry
+    }
+}
+
+/// State of the first version of the contract.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(crate) struct ContractV1 {
+    pub(crate) owner: AccountId,
+    pub(crate) locked_amount: Balance,
+    pub(crate) staking_id: Option<AccountId>,
+    pub(crate) total_delegation_amount: Balance,
+    pub(crate) delegations: LookupMap<AccountId, Balance>,
+    pub(crate) last_proposal_id: u64,
+    pub(crate) proposals: TreeMap<u64, VersionedProposal>,
+    pub(crate) registries: HashMap<AccountId, Vec<RegistryDataV1>>,
+    pub(crate) dao: AccountId,
+    pub(crate) voting_power: HashMap<u64, Vec<(AccountId, Balance, Action)>>,
+}
+
+/// Versions of the contract state `migrate` upgrades from.
+#[allow(clippy::large_enum_variant)]
+enum VersionedContract {
+    V1(ContractV1),
+    Current(Contract),
+}
+
+impl VersionedContract {
+    fn read() -> Self {
+        let state = env::storage_read(b"STATE").expect("ERR_NO_STATE");
+        if let Ok(contract) = Contract::try_from_slice(&state) {
+            return Self::Current(contract);
+        }
+        match ContractV1::try_from_slice(&state) {
+            Ok(contract) => Self::V1(contract),
+            Err(_) => env::panic_str("ERR_UNKNOWN_STATE_VERSION"),
+        }
+    }
+}
+
+impl From<ContractV1> for Contract {
+    fn from(old: ContractV1) -> Self {
+        // The first version never locked bonds, and set the owner as a placeholder staking contract.
+        let staking_id = old.staking_id.filter(|staking_id| *staking_id != old.owner);
+        let mut contract = Contract::internal_new(old.owner, old.dao);
+        contract.staking_id = staking_id;
+        contract.total_delegation_amount = old.total_delegation_amount;
+        contract.delegations = old.delegations;
+        contract.last_proposal_id = old.last_proposal_id;
+        contract.legacy_proposal_count = old.last_proposal_id;
+        contract.proposals = old.proposals;
+        contract.registries = old
+            .registries
+            .into_iter()
+            .map(|(owner, registries)| {
+                (
+                    owner,
+                    registries.into_iter().map(RegistryData::from).collect(),
+                )
+            })
+            .collect();
+        contract.voting_power = old
+            .voting_power
+            .into_iter()
+            .map(|(id, votes)| {
+                let votes = votes
+                    .into_iter()
+                    .map(|(account_id, stake, action)| LocalVote {
+                        account_id,
+                        stake,
+                        action,
+                        voted_at: 0,
+                    })
+                    .collect();
+                (id, votes)
+            })
+            .collect();
+        contract
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Upgrades the state left by an earlier version of the contract after a redeploy.
+    /// State already in the current layout is kept as is. Proposals are upgraded when read.
+    #[init(ignore_state)]
+    #[private]
+    pub fn migrate() -> Self {
+        match VersionedContract::read() {
+            VersionedContract::V1(old) => old.into(),
+            VersionedContract::Current(contract) => contract,
+        }
+    }
+
+    /// Expires a proposal created before the state migration that is still in progress.
+    /// Such proposals were never linked to a DAO proposal, so they can't be decided.
+    /// Can be called by anyone.
+    pub fn expire_legacy_proposal(&mut self, id: u64) {
+        assert!(id < self.legacy_proposal_count, "ERR_NOT_LEGACY_PROPOSAL");
+        let mut proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::InProgress,
+            "ERR_PROPOSAL_NOT_IN_PROGRESS"
+        );
+        assert!(
+            proposal.dao_proposal_id.is_none(),
+            "ERR_NOT_LEGACY_PROPOSAL"
+        );
+        self.internal_expire_proposal(id, &mut proposal);
+    }
+}
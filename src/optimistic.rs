@@ -0,0 +1,84 @@
+use near_sdk::json_types::{U128, U64};
+
+use crate::types::Vote;
+use crate::*;
+
+/// Rules for approving proposals to a registry once nobody objected for a while,
+/// without waiting for the DAO.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OptimisticPolicy {
+    /// How long after submission a proposal can be objected to, in nanoseconds.
+    pub period: U64,
+    /// Voting power of rejecting votes that blocks optimistic approval.
+    pub veto_threshold: U128,
+    /// Name of the DAO role whose members block optimistic approval with a single rejecting vote.
+    pub reviewer_role: Option<String>,
+}
+
+impl Contract {
+    /// Checks if rejecting votes on the proposal reached the veto threshold, or one of them
+    /// is from a reviewer.
+    fn internal_is_objected(
+        &self,
+        id: u64,
+        proposal: &Proposal,
+        policy: &OptimisticPolicy,
+    ) -> bool {
+        let reviewers = policy.reviewer_role.as_ref().and_then(|name| {
+            self.policy
+                .get()
+                .and_then(|dao_policy| dao_policy.roles.into_iter().find(|role| role.name == *name))
+        });
+        let mut rejected: Option<Balance> = None;
+        for vote in self.voting_power.get(&id).into_iter().flatten() {
+            if !matches!(proposal.votes.get(&vote.account_id), Some(Vote::Reject)) {
+                continue;
+            }
+            if matches!(&reviewers, Some(role) if role.kind.match_account(&vote.account_id)) {
+                return true;
+            }
+            *rejected.get_or_insert(0) += self.internal_vote_weight(proposal, vote);
+        }
+        matches!(rejected, Some(rejected) if rejected >= policy.veto_threshold.0)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets optimistic approval for proposals to given registry. `None` waits for the DAO.
+    /// Proposals keep the policy they were submitted with. Only for the registry owner.
+    pub fn set_registry_optimistic_policy(
+        &mut self,
+        unique_identifier: AccountId,
+        optimistic_policy: Option<OptimisticPolicy>,
+    ) {
+        let registry = self.internal_owned_registry_mut(&unique_identifier);
+        registry.optimistic_policy = optimistic_policy;
+    }
+
+    /// Approves proposal to an optimistic registry once its objection period is over
+    /// and nobody objected. Can be called by anyone.
+    pub fn finalize_optimistic(&mut self, id: u64) {
+        let mut proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::InProgress,
+            "ERR_PROPOSAL_NOT_IN_PROGRESS"
+        );
+        let policy = proposal
+            .optimistic_policy
+            .clone()
+            .expect("ERR_NOT_OPTIMISTIC");
+        assert!(
+            env::block_timestamp() >= proposal.submission_time.0 + policy.period.0,
+            "ERR_OBJECTION_PERIOD_NOT_OVER"
+        );
+        assert!(
+            !self.internal_is_objected(id, &proposal, &policy),
+            "ERR_PROPOSAL_OBJECTED"
+        );
+        self.internal_approve_proposal(id, &mut proposal);
+        self.proposals.insert(&id, &VersionedProposal::V2(proposal));
+    }
+}
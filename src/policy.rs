@@ -0,0 +1,136 @@
+use near_sdk::{
+    borsh::{self, BorshDeserialize, BorshSerialize},
+    json_types::{U128, U64},
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+use std::collections::HashMap;
+
+use crate::types::Action;
+
+/// Kind of role in the DAO, decides who belongs to it.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum RoleKind {
+    /// Matches everyone, who is not matched by other roles.
+    Everyone,
+    /// Member greater or equal than given balance. Can use `1` as non-zero balance.
+    Member(U128),
+    /// Set of accounts.
+    Group(Vec<AccountId>),
+}
+
+impl RoleKind {
+    /// Checks if given account is part of this role.
+    /// Token balances are not known locally, so `Member` roles never match.
+    pub fn match_account(&self, account_id: &AccountId) -> bool {
+        match self {
+            RoleKind::Everyone => true,
+            RoleKind::Member(_) => false,
+            RoleKind::Group(accounts) => accounts.contains(account_id),
+        }
+    }
+}
+
+/// Role with its permissions, as defined by the DAO.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct RolePermission {
+    /// Name of the role to display to the user.
+    pub name: String,
+    /// Kind of the role: defines which users this permissions apply.
+    pub kind: RoleKind,
+    /// Set of actions on which proposals that this role is allowed to execute.
+    /// <proposal_kind>:<action>
+    pub permissions: Vec<String>,
+    /// For each proposal kind, defines voting policy.
+    pub vote_policy: HashMap<String, VotePolicy>,
+}
+
+/// How the voting policy votes get weighted.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum WeightKind {
+    /// Using token amounts and total delegated at the moment.
+    TokenWeight,
+    /// Weight of the group role. Roles that don't have scoped group are not supported.
+    RoleWeight,
+}
+
+/// Either fixed weight or ratio of the total.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+#[serde(untagged)]
+pub enum WeightOrRatio {
+    Weight(U128),
+    Ratio(u64, u64),
+}
+
+/// Defines configuration of the vote.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct VotePolicy {
+    /// Kind of weight to use for votes.
+    pub weight_kind: WeightKind,
+    /// Minimum number required for vote to finalize.
+    pub quorum: U128,
+    /// How many votes to pass this vote.
+    pub threshold: WeightOrRatio,
+}
+
+/// Local copy of the DAO policy, as returned by its `get_policy`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct DaoPolicy {
+    /// List of roles and permissions for them in the current policy.
+    pub roles: Vec<RolePermission>,
+    /// Default vote policy. Used when given proposal kind doesn't have special policy.
+    pub default_vote_policy: VotePolicy,
+    /// Proposal bond.
+    pub proposal_bond: U128,
+    /// Expiration period for proposals.
+    pub proposal_period: U64,
+    /// Bond for claiming a bounty.
+    pub bounty_bond: U128,
+    /// Period in which giving up on bounty is not punished.
+    pub bounty_forgiveness_period: U64,
+    /// When this copy was fetched from the DAO. Not part of the DAO policy.
+    #[serde(default)]
+    pub fetched_at: Option<U64>,
+}
+
+impl DaoPolicy {
+    /// Checks if any role of given account allows `action` on proposals of given kind.
+    pub fn can_execute_action(
+        &self,
+        account_id: &AccountId,
+        proposal_kind_label: &str,
+        action: &Action,
+    ) -> bool {
+        let action = action.to_policy_label();
+        let allowed = [
+            format!("{}:{}", proposal_kind_label, action),
+            format!("{}:*", proposal_kind_label),
+            format!("*:{}", action),
+            "*:*".to_string(),
+        ];
+        self.roles.iter().any(|role| {
+            role.kind.match_account(account_id)
+                && role
+                    .permissions
+                    .iter()
+                    .any(|permission| allowed.contains(permission))
+        })
+    }
+
+    /// Time after which voting on a proposal submitted at `submission_time` is over.
+    pub fn voting_deadline(&self, submission_time: U64) -> U64 {
+        U64(submission_time.0 + self.proposal_period.0)
+    }
+}
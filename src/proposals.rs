@@ -1,17 +1,26 @@
 use near_contract_standards::fungible_token::core_impl::ext_fungible_token;
-use near_sdk::{json_types::U128, AccountId, Balance, Promise, PromiseOrValue, PromiseResult};
+use near_sdk::{
+    json_types::{U128, U64},
+    AccountId, Balance, Promise, PromiseOrValue, PromiseResult,
+};
+use std::ops::Bound;
 
 use crate::{
-    consts::{GAS_FOR_FT_TRANSFER, GAS_FOR_RESOLVE_TRANSFER, NO_DEPOSIT, ONE_NEAR, ONE_YOCTO_NEAR},
+    consts::{
+        GAS_FOR_FT_TRANSFER, GAS_FOR_RESOLVE_TRANSFER, GAS_FOR_TOKEN_GATE_CALLBACK,
+        LOCAL_VOTES_ROLE, NO_DEPOSIT, ONE_NEAR, ONE_YOCTO_NEAR,
+    },
+    strategies::VoteStrategy,
     types::{
-        Action, Proposal, ProposalInput, ProposalInputAstroDao, ProposalStatus, VersionedProposal,
+        convert_old_to_new_token, Action, AmendPolicy, LocalVote, PayoutStatus, Proposal,
+        ProposalInput, ProposalInputAstroDao, ProposalKind, ProposalStatus, VersionedProposal,
+        Vote, WithdrawPolicy,
     },
     *,
 };
 
 impl Contract {
     /// Execute payout of given token to given user.
-    #[allow(dead_code)]
     pub(crate) fn internal_payout(
         &mut self,
         token_id: &Option<AccountId>,
@@ -47,6 +56,68 @@ impl Contract {
         }
     }
 
+    /// Pays out approved `Transfer`, `BountyDone` or `DisputeRow` proposal from this contract.
+    /// Its status is settled by `on_proposal_callback` once the payout finished.
+    /// Returns false if the payout failed right away, other kinds pay nothing.
+    pub(crate) fn internal_execute_payout(&mut self, id: u64, proposal: &Proposal) -> bool {
+        let (token_id, receiver_id, amount, msg) = match &proposal.kind {
+            ProposalKind::Transfer {
+                token_id,
+                receiver_id,
+                amount,
+                msg,
+            } => (
+                convert_old_to_new_token(token_id),
+                receiver_id.clone(),
+                amount.0,
+                msg.clone(),
+            ),
+            ProposalKind::BountyDone { bounty_id } => {
+                let bounty = self.bounties.get(bounty_id).expect("ERR_NO_BOUNTY");
+                (
+                    bounty.token,
+                    proposal.proposer.clone(),
+                    bounty.amount.0,
+                    None,
+                )
+            }
+            ProposalKind::DisputeRow { bond, .. } => {
+                let reward = self
+                    .internal_find_registry(&proposal.unique_identifier)
+                    .and_then(|registry| registry.challenge_policy.clone())
+                    .map(|policy| policy.reward.0)
+                    .unwrap_or_default();
+                (None, proposal.proposer.clone(), bond.0 + reward, None)
+            }
+            _ => return true,
+        };
+        if token_id.is_none() {
+            // Funds reserved for this payout are excluded from the available balance.
+            let reserved = self.internal_payout_reserve(proposal);
+            if self.internal_available_balance() + reserved < amount {
+                env::log_str("ERR_NOT_ENOUGH_BALANCE");
+                self.payouts.insert(&id, &PayoutStatus::Failed);
+                return false;
+            }
+        }
+        self.payouts.insert(&id, &PayoutStatus::Pending);
+        if let PromiseOrValue::Promise(promise) = self.internal_payout(
+            &token_id,
+            &receiver_id,
+            amount,
+            proposal.description.clone(),
+            &msg,
+        ) {
+            let _ = promise.then(ext_self::on_proposal_callback(
+                id,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ));
+        }
+        true
+    }
+
     pub(crate) fn internal_callback_proposal_success(
         &mut self,
         proposal: &mut Proposal,
@@ -55,6 +126,440 @@ impl Contract {
         PromiseOrValue::Value(())
     }
 
+    /// Bond the DAO requires for a new proposal.
+    /// Falls back to `ONE_NEAR` until the DAO policy is cached.
+    pub(crate) fn internal_proposal_bond(&self) -> Balance {
+        self.policy
+            .get()
+            .map(|policy| policy.proposal_bond.0)
+            .unwrap_or(ONE_NEAR)
+    }
+
+    /// Checks that the DAO policy lets this contract add proposal of given kind.
+    /// Skipped until the DAO policy is cached.
+    pub(crate) fn internal_check_proposal_kind(
+        &self,
+        kind: &ProposalKind,
+    ) -> Result<(), &'static str> {
+        match self.policy.get() {
+            Some(policy)
+                if !policy.can_execute_action(
+                    &env::current_account_id(),
+                    kind.to_policy_label(),
+                    &Action::AddProposal,
+                ) =>
+            {
+                Err("ERR_PERMISSION_DENIED")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks bond, kind and content of proposal of given proposer.
+    /// Returns the proposal with the settings of its registry at submission.
+    pub(crate) fn internal_check_proposal(
+        &self,
+        proposer: &AccountId,
+        proposal: ProposalInput,
+        bond: Balance,
+    ) -> Result<Proposal, &'static str> {
+        if bond < self.internal_proposal_bond() {
+            return Err("ERR_MIN_BOND");
+        }
+        self.internal_check_proposal_kind(&proposal.kind.to_dao_kind())?;
+        let mut proposal: Proposal = proposal.into();
+        proposal.proposer = proposer.clone();
+        if let Some(registry) = self.internal_find_registry(&proposal.unique_identifier) {
+            proposal.optimistic_policy = registry.optimistic_policy.clone();
+            proposal.commit_reveal_policy = registry.commit_reveal_policy.clone();
+            proposal.vote_strategy = registry.vote_strategy.clone();
+        }
+        self.internal_validate_proposal(&proposal)?;
+        Ok(proposal)
+    }
+
+    /// Locks bond attached by the proposer until the proposal is finalized.
+    pub(crate) fn internal_lock_bond(&mut self, account_id: &AccountId, amount: Balance) {
+        self.locked_amount += amount;
+        let locked = self.locked_bonds.get(account_id).unwrap_or_default();
+        self.locked_bonds.insert(account_id, &(locked + amount));
+    }
+
+    /// Unlocks bond of given proposal.
+    /// If `refund` is set, bond is returned to the proposer, otherwise it is forfeited to the DAO.
+    pub(crate) fn internal_release_bond(&mut self, proposal: &mut Proposal, refund: bool) {
+        let amount = self.internal_unlock_bond(proposal);
+        if refund && amount > 0 {
+            Promise::new(proposal.proposer.clone()).transfer(amount);
+        }
+    }
+
+    /// Unlocks bond of given proposal without paying it out. Returns unlocked amount.
+    pub(crate) fn internal_unlock_bond(&mut self, proposal: &mut Proposal) -> Balance {
+        let amount = proposal.bond.0;
+        if amount == 0 {
+            return 0;
+        }
+        proposal.bond = U128(0);
+        self.locked_amount -= amount;
+        let locked = self
+            .locked_bonds
+            .get(&proposal.proposer)
+            .unwrap_or_default()
+            - amount;
+        if locked == 0 {
+            self.locked_bonds.remove(&proposal.proposer);
+        } else {
+            self.locked_bonds.insert(&proposal.proposer, &locked);
+        }
+        amount
+    }
+
+    /// End of the voting period of a proposal submitted at `submission_time`.
+    /// Uses the configured voting period, or the DAO policy if none is configured.
+    pub(crate) fn internal_voting_deadline(&self, submission_time: U64) -> Option<U64> {
+        match self.voting_period {
+            Some(period) => Some(U64(submission_time.0 + period.0)),
+            None => self
+                .policy
+                .get()
+                .map(|policy| policy.voting_deadline(submission_time)),
+        }
+    }
+
+    /// Checks if voting period of given proposal is over.
+    pub(crate) fn internal_is_voting_over(&self, proposal: &Proposal) -> bool {
+        matches!(
+            self.internal_voting_deadline(proposal.submission_time),
+            Some(deadline) if env::block_timestamp() >= deadline.0
+        )
+    }
+
+    /// Checks if voting period of given proposal in progress is over.
+    /// Commit-reveal proposals are decided by `tally_votes` instead. Mirrored proposals
+    /// with non-linear weights and local votes are decided at the deadline by `relay_votes`.
+    pub(crate) fn internal_is_expired(&self, proposal: &Proposal) -> bool {
+        proposal.status == ProposalStatus::InProgress
+            && proposal.commit_reveal_policy.is_none()
+            && !(proposal.is_mirrored()
+                && proposal.vote_strategy != VoteStrategy::Linear
+                && !proposal.votes.is_empty())
+            && self.internal_is_voting_over(proposal)
+    }
+
+    /// Moves proposal to `Expired`, returning the bond and finalizing it in the DAO.
+    pub(crate) fn internal_expire_proposal(&mut self, id: u64, proposal: &mut Proposal) {
+        proposal.status = ProposalStatus::Expired;
+        self.internal_release_bond(proposal, true);
+        self.internal_refund_vote_commits(id);
+        self.internal_close_dispute(proposal, true);
+        if let Some(dao_proposal_id) = proposal.dao_proposal_id {
+            let _ = Promise::new(self.dao.clone()).function_call(
+                "act_proposal".to_string(),
+                json!({ "id": dao_proposal_id, "action": Action::Finalize })
+                    .to_string()
+                    .as_bytes()
+                    .to_vec(),
+                NO_DEPOSIT,
+                GAS_FOR_FT_TRANSFER,
+            );
+        }
+        self.proposals
+            .insert(&id, &VersionedProposal::V2(proposal.clone()));
+    }
+
+    /// Records vote of given account, weighted by voting power at proposal submission.
+    /// On mirrored proposals the vote stays `Vote::InProgress` until the aggregated vote
+    /// is accepted by the DAO.
+    pub(crate) fn internal_record_vote(
+        &mut self,
+        id: u64,
+        proposal: &mut Proposal,
+        voter: AccountId,
+        action: &Action,
+        amount: Balance,
+    ) {
+        assert!(
+            matches!(action, Action::VoteApprove | Action::VoteReject),
+            "ERR_WRONG_ACTION"
+        );
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::InProgress,
+            "ERR_PROPOSAL_NOT_READY_FOR_VOTE"
+        );
+        assert!(proposal.relayed_vote.is_none(), "ERR_VOTES_RELAYED");
+        let power = self.internal_voting_power_at(&voter, proposal.submission_time.0);
+        let stake = if amount == 0 {
+            power
+        } else {
+            assert!(amount <= power, "ERR_NOT_ENOUGH_VOTING_POWER");
+            amount
+        };
+        assert!(stake > 0, "ERR_NO_VOTING_POWER");
+        let votes = self.voting_power.entry(id).or_default();
+        assert!(
+            votes.iter().all(|vote| vote.account_id != voter),
+            "ERR_ALREADY_VOTED"
+        );
+        votes.push(LocalVote {
+            account_id: voter.clone(),
+            stake,
+            action: action.clone(),
+            voted_at: env::block_timestamp(),
+        });
+        if proposal.is_mirrored() {
+            proposal.votes.insert(voter, Vote::InProgress);
+        } else {
+            proposal.votes.insert(voter, action.clone().into());
+            self.internal_count_local_votes(id, proposal);
+        }
+    }
+
+    /// Sets every local vote of the proposal to its action, or back to `Vote::InProgress`.
+    pub(crate) fn internal_confirm_local_votes(
+        &self,
+        id: u64,
+        proposal: &mut Proposal,
+        confirmed: bool,
+    ) {
+        for local_vote in self.voting_power.get(&id).into_iter().flatten() {
+            let vote = if confirmed {
+                local_vote.action.clone().into()
+            } else {
+                Vote::InProgress
+            };
+            proposal.votes.insert(local_vote.account_id.clone(), vote);
+        }
+    }
+
+    /// Outcome of local votes once the voting power that didn't vote yet can't change it.
+    /// With non-linear weights that is only known once the voting period is over.
+    pub(crate) fn internal_local_decision(&self, id: u64, proposal: &Proposal) -> Option<Action> {
+        let votes = self.voting_power.get(&id)?;
+        let (mut approve, mut reject, mut cast) = (0, 0, 0);
+        for local_vote in votes {
+            let weight = self.internal_vote_weight(proposal, local_vote);
+            match local_vote.action {
+                Action::VoteApprove => approve += weight,
+                _ => reject += weight,
+            }
+            cast += local_vote.stake;
+        }
+        // Linear weight is the stake, so the remaining stake bounds the rest. Quadratic weight
+        // of split stake and conviction weight over time aren't bounded by it.
+        let remaining = match proposal.vote_strategy {
+            VoteStrategy::Linear => proposal.total_voting_power.0.saturating_sub(cast),
+            _ if self.internal_is_voting_over(proposal) => 0,
+            _ => return None,
+        };
+        if approve > reject + remaining {
+            Some(Action::VoteApprove)
+        } else if reject >= approve + remaining {
+            Some(Action::VoteReject)
+        } else {
+            None
+        }
+    }
+
+    /// Recounts weights of confirmed local votes into `vote_counts`.
+    pub(crate) fn internal_count_local_votes(&self, id: u64, proposal: &mut Proposal) {
+        let mut counts = [0; 3];
+        for local_vote in self.voting_power.get(&id).into_iter().flatten() {
+            match proposal.votes.get(&local_vote.account_id) {
+                Some(Vote::InProgress) | None => {}
+                Some(vote) => {
+                    counts[vote.clone() as usize] += self.internal_vote_weight(proposal, local_vote)
+                }
+            }
+        }
+        proposal
+            .vote_counts
+            .insert(LOCAL_VOTES_ROLE.to_string(), counts);
+    }
+
+    /// Adds proposal of given proposer locally and mirrors it in the DAO, forwarding given bond.
+    /// Proposals to optimistic or commit-reveal registries are decided here and keep the bond
+    /// in this contract.
+    pub(crate) fn internal_add_proposal(
+        &mut self,
+        proposer: &AccountId,
+        proposal: ProposalInput,
+        bond: Balance,
+    ) -> u64 {
+        // 0. validate bond attached, kind and content.
+        let proposal = self
+            .internal_check_proposal(proposer, proposal, bond)
+            .unwrap_or_else(|err| env::panic_str(err));
+        self.internal_insert_proposal(proposal, bond)
+    }
+
+    /// Stores proposal checked by `internal_check_proposal` and mirrors it in the DAO if needed.
+    pub(crate) fn internal_insert_proposal(
+        &mut self,
+        mut proposal: Proposal,
+        bond: Balance,
+    ) -> u64 {
+        // 1. Mirror proposal in the DAO, forwarding the bond.
+        let id = self.last_proposal_id;
+        if proposal.is_mirrored() {
+            let dao_proposal = ProposalInputAstroDao {
+                description: proposal.description.clone(),
+                kind: proposal.kind.to_dao_kind(),
+            };
+            let _ = Promise::new(self.dao.clone())
+                .function_call(
+                    "add_proposal".to_string(),
+                    json!({ "proposal": dao_proposal })
+                        .to_string()
+                        .as_bytes()
+                        .to_vec(),
+                    bond,
+                    GAS_FOR_FT_TRANSFER,
+                )
+                .then(ext_self::callback_add_proposal_result(
+                    id,
+                    env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_RESOLVE_TRANSFER,
+                ));
+        }
+
+        // 2. Lock the bond.
+        proposal.bond = U128(bond);
+        proposal.total_voting_power = U128(self.total_delegation_amount);
+        self.internal_lock_bond(&proposal.proposer, bond);
+
+        // 3. Actually add proposal to the current list of proposals.
+        self.proposals.insert(&id, &VersionedProposal::V2(proposal));
+        self.last_proposal_id += 1;
+        id
+    }
+
+    /// Records vote of given account, to be relayed to the DAO by `relay_votes`.
+    pub(crate) fn internal_act_proposal(
+        &mut self,
+        id: u64,
+        voter: AccountId,
+        action: Action,
+        amount: U128,
+    ) {
+        let mut proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        if self.internal_is_expired(&proposal) {
+            self.internal_expire_proposal(id, &mut proposal);
+            env::log_str("Proposal expired, vote is not recorded");
+            return;
+        }
+        assert!(
+            !self.internal_is_voting_over(&proposal),
+            "ERR_VOTING_PERIOD_OVER"
+        );
+        assert!(
+            proposal.commit_reveal_policy.is_none(),
+            "ERR_COMMIT_REVEAL_VOTING"
+        );
+        self.internal_record_vote(id, &mut proposal, voter, &action, amount.0);
+        self.proposals.insert(&id, &VersionedProposal::V2(proposal));
+    }
+
+    /// Asks the DAO to remove proposal of the caller while it is in progress.
+    /// The proposal is moved to given status by `on_withdraw_proposal_callback`
+    /// once the DAO removed it, or right away if it isn't mirrored in the DAO.
+    pub(crate) fn internal_withdraw_proposal(
+        &mut self,
+        id: u64,
+        proposal: &Proposal,
+        status: ProposalStatus,
+    ) -> PromiseOrValue<U128> {
+        assert_eq!(
+            proposal.proposer,
+            env::predecessor_account_id(),
+            "ERR_NOT_ALLOWED"
+        );
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::InProgress,
+            "ERR_PROPOSAL_NOT_IN_PROGRESS"
+        );
+        assert!(proposal.amended_by.is_none(), "ERR_PROPOSAL_AMENDED");
+        if !proposal.is_mirrored() {
+            let mut proposal = proposal.clone();
+            let refund = self.internal_finish_withdraw(id, &mut proposal, status);
+            self.proposals.insert(&id, &VersionedProposal::V2(proposal));
+            return PromiseOrValue::Value(U128(refund));
+        }
+        let dao_proposal_id = proposal.dao_proposal_id.expect("ERR_PROPOSAL_NOT_IN_DAO");
+        Promise::new(self.dao.clone())
+            .function_call(
+                "act_proposal".to_string(),
+                json!({ "id": dao_proposal_id, "action": Action::RemoveProposal })
+                    .to_string()
+                    .as_bytes()
+                    .to_vec(),
+                NO_DEPOSIT,
+                GAS_FOR_FT_TRANSFER,
+            )
+            .then(ext_self::on_withdraw_proposal_callback(
+                id,
+                status,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+            .into()
+    }
+
+    /// Moves withdrawn proposal to given status and refunds its bond following the
+    /// withdraw policy. The rest of the bond is forfeited. Returns refunded amount.
+    pub(crate) fn internal_finish_withdraw(
+        &mut self,
+        id: u64,
+        proposal: &mut Proposal,
+        status: ProposalStatus,
+    ) -> Balance {
+        proposal.status = status;
+        self.internal_close_dispute(proposal, true);
+        self.internal_refund_vote_commits(id);
+        let policy_refund = self.withdraw_policy.refund_amount(
+            proposal.bond.0,
+            proposal.submission_time,
+            !proposal.votes.is_empty(),
+        );
+        self.internal_unlock_bond(proposal);
+        // The DAO keeps bonds of mirrored proposals, their refund comes from this contract.
+        let refund = policy_refund.min(self.internal_available_balance());
+        if refund < policy_refund {
+            env::log_str("Not enough balance to refund the whole bond");
+        }
+        if refund > 0 {
+            Promise::new(proposal.proposer.clone()).transfer(refund);
+        }
+        refund
+    }
+
+    /// Balance of this contract not needed for storage, locked bonds or reserved funds.
+    pub(crate) fn internal_available_balance(&self) -> Balance {
+        let storage_cost = env::storage_byte_cost() * env::storage_usage() as Balance;
+        env::account_balance()
+            .saturating_sub(storage_cost)
+            .saturating_sub(self.locked_amount)
+            .saturating_sub(self.reserved_amount)
+    }
+
+    /// Part of the payout of given proposal already held in `reserved_amount`.
+    pub(crate) fn internal_payout_reserve(&self, proposal: &Proposal) -> Balance {
+        match &proposal.kind {
+            ProposalKind::BountyDone { bounty_id } => self
+                .bounties
+                .get(bounty_id)
+                .filter(|bounty| bounty.token.is_none())
+                .map(|bounty| bounty.amount.0)
+                .unwrap_or_default(),
+            ProposalKind::DisputeRow { bond, .. } => bond.0,
+            _ => 0,
+        }
+    }
+
     pub(crate) fn internal_callback_proposal_fail(
         &mut self,
         proposal: &mut Proposal,
@@ -67,57 +572,389 @@ impl Contract {
 #[near_bindgen]
 impl Contract {
     /// Add proposal to this DAO.
+    /// Attached deposit is forwarded to the DAO as the proposal bond and stays locked
+    /// on behalf of the proposer until the proposal is finalized.
+    /// For token-gated registries the proposal is checked right away and added once the
+    /// proposer's holdings are checked, `None` if they aren't enough.
     #[payable]
-    pub fn add_proposal(&mut self, proposal: ProposalInput) -> u64 {
-        // 0. validate bond attached.
-        let dao_proposal = ProposalInputAstroDao {
-            description: proposal.description.clone(),
-            kind: proposal.kind.clone(),
-        };
-        let _ = Promise::new(self.dao.clone())
+    pub fn add_proposal(&mut self, proposal: ProposalInput) -> PromiseOrValue<Option<u64>> {
+        assert!(
+            !matches!(proposal.kind, ProposalKind::DisputeRow { .. }),
+            "ERR_USE_CHALLENGE_ROW"
+        );
+        let proposer = env::predecessor_account_id();
+        match self.internal_token_gate(&proposal.unique_identifier) {
+            Some(gate) => {
+                if let Err(err) = self.internal_check_proposal(
+                    &proposer,
+                    proposal.clone(),
+                    env::attached_deposit(),
+                ) {
+                    env::panic_str(err);
+                }
+                PromiseOrValue::Promise(self.internal_query_token_gate(&gate, &proposer).then(
+                    ext_self::on_token_gate_proposal_callback(
+                        proposer,
+                        proposal,
+                        U128(env::attached_deposit()),
+                        env::current_account_id(),
+                        NO_DEPOSIT,
+                        GAS_FOR_TOKEN_GATE_CALLBACK,
+                    ),
+                ))
+            }
+            None => PromiseOrValue::Value(Some(self.internal_add_proposal(
+                &proposer,
+                proposal,
+                env::attached_deposit(),
+            ))),
+        }
+    }
+
+    /// Act on given proposal by id, if permissions allow.
+    /// Vote is weighted locally by voting power of the caller at proposal submission.
+    /// The DAO only sees the aggregated vote of this contract cast by `relay_votes`.
+    /// Memo is logged but not stored in the state. Can be used to leave notes or explain the action.
+    pub fn act_proposal(&mut self, id: u64, action: Action, amount: U128) {
+        let proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        let voter = env::predecessor_account_id();
+        match self.internal_token_gate(&proposal.unique_identifier) {
+            Some(gate) => {
+                let _ = self.internal_query_token_gate(&gate, &voter).then(
+                    ext_self::on_token_gate_vote_callback(
+                        id,
+                        voter,
+                        action,
+                        amount,
+                        env::current_account_id(),
+                        NO_DEPOSIT,
+                        GAS_FOR_TOKEN_GATE_CALLBACK,
+                    ),
+                );
+            }
+            None => self.internal_act_proposal(id, voter, action, amount),
+        }
+    }
+
+    /// Withdraws proposal of the caller while it is in progress.
+    /// The proposal is removed from the DAO, which keeps the bond. The withdraw policy
+    /// refund is paid from the available balance of this contract once the DAO removed it.
+    /// Proposals not mirrored in the DAO are withdrawn right away.
+    pub fn withdraw_proposal(&mut self, id: u64) -> PromiseOrValue<U128> {
+        let proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        self.internal_withdraw_proposal(id, &proposal, ProposalStatus::Cancelled)
+    }
+
+    /// Replaces proposal of the caller with a new revision while it is in progress.
+    /// The original proposal is withdrawn as by `withdraw_proposal` and moved to the
+    /// new revision once the DAO removed it. The bond of the revision is attached the same
+    /// way as for `add_proposal`. Local votes are carried over if the amend policy preserves them,
+    /// pending until they are relayed for the revision by `relay_votes`.
+    #[payable]
+    pub fn amend_proposal(&mut self, id: u64, proposal: ProposalInput) -> u64 {
+        let original: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        assert!(
+            !matches!(original.kind, ProposalKind::DisputeRow { .. })
+                && !matches!(proposal.kind, ProposalKind::DisputeRow { .. }),
+            "ERR_CANT_AMEND_DISPUTE"
+        );
+        let _ = self.internal_withdraw_proposal(id, &original, ProposalStatus::Moved);
+        assert!(
+            proposal.unique_identifier == original.unique_identifier
+                || self
+                    .internal_token_gate(&proposal.unique_identifier)
+                    .is_none(),
+            "ERR_TOKEN_GATED"
+        );
+        let revision_id = self.internal_add_proposal(
+            &env::predecessor_account_id(),
+            proposal,
+            env::attached_deposit(),
+        );
+        let mut revision: Proposal = self.proposals.get(&revision_id).unwrap().into();
+        revision.amends = Some(id);
+        if self.amend_policy.preserve_votes {
+            if let Some(votes) = self.voting_power.get(&id).cloned() {
+                for vote in &votes {
+                    revision
+                        .votes
+                        .insert(vote.account_id.clone(), Vote::InProgress);
+                }
+                self.voting_power.insert(revision_id, votes);
+            }
+            self.internal_count_local_votes(revision_id, &mut revision);
+        }
+        // Proposals not mirrored in the DAO are already moved.
+        let mut original: Proposal = self.proposals.get(&id).unwrap().into();
+        original.amended_by = Some(revision_id);
+        self.proposals.insert(&id, &VersionedProposal::V2(original));
+        self.proposals
+            .insert(&revision_id, &VersionedProposal::V2(revision));
+        revision_id
+    }
+
+    /// Applies queued proposal once the timelock of its registry elapsed. Can be called by anyone.
+    pub fn execute_proposal(&mut self, id: u64) {
+        let mut proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::Queued,
+            "ERR_PROPOSAL_NOT_QUEUED"
+        );
+        assert!(
+            env::block_timestamp() >= proposal.executable_at.unwrap().0,
+            "ERR_TIMELOCK_NOT_ELAPSED"
+        );
+        self.internal_apply_proposal(id, &mut proposal);
+        self.proposals.insert(&id, &VersionedProposal::V2(proposal));
+    }
+
+    /// Vetoes queued proposal. Only for the guardian of its registry.
+    pub fn veto_proposal(&mut self, id: u64) {
+        let mut proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::Queued,
+            "ERR_PROPOSAL_NOT_QUEUED"
+        );
+        let guardian = self
+            .internal_find_registry(&proposal.unique_identifier)
+            .and_then(|registry| registry.guardian.clone());
+        assert_eq!(
+            guardian,
+            Some(env::predecessor_account_id()),
+            "ERR_NOT_ALLOWED"
+        );
+        self.internal_record_rejected(&proposal);
+        self.internal_close_dispute(&proposal, true);
+        proposal.status = ProposalStatus::Vetoed;
+        self.proposals.insert(&id, &VersionedProposal::V2(proposal));
+    }
+
+    /// Sets voting period of proposals, in nanoseconds.
+    /// If not set, the proposal period of the DAO policy is used.
+    pub fn set_voting_period(&mut self, voting_period: Option<U64>) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "ERR_NOT_ALLOWED");
+        self.voting_period = voting_period;
+    }
+
+    /// Expires overdue proposals and archives finished ones among up to `limit` proposals
+    /// starting from `from_index`, releasing their storage. Can be called by anyone.
+    /// Returns ids of archived proposals.
+    pub fn cleanup_proposals(&mut self, from_index: u64, limit: u64) -> Vec<u64> {
+        let ids: Vec<u64> = self
+            .proposals
+            .range((Bound::Included(from_index), Bound::Unbounded))
+            .map(|(id, _)| id)
+            .take(limit as usize)
+            .collect();
+        let mut archived = Vec::new();
+        for id in ids {
+            let mut proposal: Proposal = self.proposals.get(&id).unwrap().into();
+            if self.internal_is_expired(&proposal) {
+                self.internal_expire_proposal(id, &mut proposal);
+            }
+            if matches!(
+                proposal.status,
+                ProposalStatus::InProgress | ProposalStatus::Failed | ProposalStatus::Queued
+            ) || self.payouts.contains_key(&id)
+            {
+                continue;
+            }
+            self.proposals.remove(&id);
+            self.voting_power.remove(&id);
+            self.internal_refund_vote_commits(id);
+            self.archived_proposals.insert(&id, &proposal.into());
+            archived.push(id);
+        }
+        archived
+    }
+
+    /// Sets whether amended proposals keep their local votes.
+    pub fn set_amend_policy(&mut self, amend_policy: AmendPolicy) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "ERR_NOT_ALLOWED");
+        self.amend_policy = amend_policy;
+    }
+
+    /// Sets rules for refunding bonds of withdrawn proposals.
+    pub fn set_withdraw_policy(&mut self, withdraw_policy: WithdrawPolicy) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "ERR_NOT_ALLOWED");
+        assert!(withdraw_policy.refund_bps <= 10_000, "ERR_INVALID_REFUND");
+        self.withdraw_policy = withdraw_policy;
+    }
+
+    /// Casts the single vote of this contract in the DAO, following the weighted local votes.
+    /// Can be called by anyone once the voting power that didn't vote can't change the outcome.
+    /// Local votes are closed from then on and confirmed once the DAO accepted the vote.
+    pub fn relay_votes(&mut self, id: u64) -> Promise {
+        let mut proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::InProgress,
+            "ERR_PROPOSAL_NOT_IN_PROGRESS"
+        );
+        assert!(!self.internal_is_expired(&proposal), "ERR_PROPOSAL_EXPIRED");
+        assert!(proposal.relayed_vote.is_none(), "ERR_VOTES_RELAYED");
+        let dao_proposal_id = proposal.dao_proposal_id.expect("ERR_PROPOSAL_NOT_IN_DAO");
+        let action = self
+            .internal_local_decision(id, &proposal)
+            .expect("ERR_VOTES_NOT_DECISIVE");
+        proposal.relayed_vote = Some(action.clone());
+        self.proposals.insert(&id, &VersionedProposal::V2(proposal));
+        Promise::new(self.dao.clone())
             .function_call(
-                "add_proposal".to_string(),
-                json!({ "proposal": dao_proposal })
+                "act_proposal".to_string(),
+                json!({ "id": dao_proposal_id, "action": action })
                     .to_string()
                     .as_bytes()
                     .to_vec(),
-                ONE_NEAR,
+                NO_DEPOSIT,
                 GAS_FOR_FT_TRANSFER,
             )
-            .then(ext_self::callback_add_proposal_result(
+            .then(ext_self::on_relay_votes_callback(
+                id,
                 env::current_account_id(),
                 NO_DEPOSIT,
                 GAS_FOR_RESOLVE_TRANSFER,
-            ));
+            ))
+    }
 
-        // 3. Actually add proposal to the current list of proposals.
+    /// Confirms local votes once the DAO accepted the relayed vote,
+    /// otherwise reopens them so they can be relayed again.
+    #[private]
+    pub fn on_relay_votes_callback(&mut self, proposal_id: u64) -> bool {
+        assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
+        let mut proposal: Proposal = self
+            .proposals
+            .get(&proposal_id)
+            .expect("ERR_NO_PROPOSAL")
+            .into();
+        let accepted = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => true,
+            PromiseResult::Failed => {
+                proposal.relayed_vote = None;
+                false
+            }
+        };
+        self.internal_confirm_local_votes(proposal_id, &mut proposal, accepted);
+        self.internal_count_local_votes(proposal_id, &mut proposal);
+        self.proposals
+            .insert(&proposal_id, &VersionedProposal::V2(proposal));
+        accepted
+    }
 
-        self.proposals.insert(
-            &self.last_proposal_id,
-            &VersionedProposal::Default(proposal.into()),
-        );
-        self.locked_amount += env::attached_deposit();
-        self.last_proposal_id
+    /// Moves withdrawn proposal to given status once the DAO removed it, forfeiting the bond.
+    /// The withdraw policy refund is paid from the available balance of this contract.
+    /// If the DAO didn't remove it, the proposal stays in progress and an amend is undone.
+    /// Returns refunded amount.
+    #[private]
+    pub fn on_withdraw_proposal_callback(
+        &mut self,
+        proposal_id: u64,
+        status: ProposalStatus,
+    ) -> U128 {
+        assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
+        let mut proposal: Proposal = self
+            .proposals
+            .get(&proposal_id)
+            .expect("ERR_NO_PROPOSAL")
+            .into();
+        if let PromiseResult::Failed = env::promise_result(0) {
+            if let Some(revision_id) = proposal.amended_by.take() {
+                let mut revision: Proposal = self.proposals.get(&revision_id).unwrap().into();
+                revision.amends = None;
+                self.proposals
+                    .insert(&revision_id, &VersionedProposal::V2(revision));
+            }
+            self.proposals
+                .insert(&proposal_id, &VersionedProposal::V2(proposal));
+            return U128(0);
+        }
+        let refund = self.internal_finish_withdraw(proposal_id, &mut proposal, status);
+        self.proposals
+            .insert(&proposal_id, &VersionedProposal::V2(proposal));
+        U128(refund)
     }
 
-    /// Act on given proposal by id, if permissions allow.
-    /// Memo is logged but not stored in the state. Can be used to leave notes or explain the action.
-    pub fn act_proposal(&mut self, id: u64, action: Action, amount: U128) {
-        let _ = Promise::new(self.dao.clone()).function_call(
-            "act_proposal".to_string(),
-            json!({ "id": id, "action": action, "amount": amount })
-                .to_string()
-                .as_bytes()
-                .to_vec(),
-            NO_DEPOSIT,
-            GAS_FOR_FT_TRANSFER,
+    /// Pulls votes of the proposal from the DAO to fix drift of the local copy.
+    pub fn reconcile_votes(&mut self, id: u64) -> Promise {
+        let proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        let dao_proposal_id = proposal.dao_proposal_id.expect("ERR_PROPOSAL_NOT_IN_DAO");
+        Promise::new(self.dao.clone())
+            .function_call(
+                "get_proposal".to_string(),
+                json!({ "id": dao_proposal_id })
+                    .to_string()
+                    .as_bytes()
+                    .to_vec(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            )
+            .then(ext_self::on_reconcile_votes_callback(
+                id,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+    }
+
+    /// Applies votes of the DAO to the local copy of the proposal.
+    /// Local votes are confirmed only if the DAO holds the vote of this contract,
+    /// otherwise they are reopened for `relay_votes`. Votes cast directly in the DAO
+    /// are copied as is.
+    #[private]
+    pub fn on_reconcile_votes_callback(&mut self, proposal_id: u64) -> HashMap<AccountId, Vote> {
+        assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
+        let result = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(val) => {
+                near_sdk::serde_json::from_slice::<ProposalOutput>(&val)
+                    .unwrap_or_else(|_| env::panic_str("ERR_WRONG_VAL_RECEIVED"))
+            }
+            PromiseResult::Failed => env::panic_str("ERR_CALL_FAILED"),
+        };
+        let mut proposal: Proposal = self
+            .proposals
+            .get(&proposal_id)
+            .expect("ERR_NO_PROPOSAL")
+            .into();
+        proposal.relayed_vote = match result.votes.get(&env::current_account_id()) {
+            Some(Vote::Approve) => Some(Action::VoteApprove),
+            Some(Vote::Reject) => Some(Action::VoteReject),
+            _ => None,
+        };
+        let accepted = proposal.relayed_vote.is_some();
+        self.internal_confirm_local_votes(proposal_id, &mut proposal, accepted);
+        for (voter, vote) in result.votes {
+            if voter != env::current_account_id() {
+                proposal.votes.insert(voter, vote);
+            }
+        }
+        proposal.vote_counts = result.vote_counts;
+        self.internal_count_local_votes(proposal_id, &mut proposal);
+        self.proposals
+            .insert(&proposal_id, &VersionedProposal::V2(proposal.clone()));
+        proposal.votes
+    }
+
+    /// Pays out approved proposal again after its payout failed. Can be called by anyone.
+    pub fn retry_payout(&mut self, id: u64) {
+        let mut proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        assert_eq!(
+            self.payouts.get(&id),
+            Some(PayoutStatus::Failed),
+            "ERR_PAYOUT_NOT_FAILED"
         );
+        if self.internal_execute_payout(id, &proposal) {
+            self.internal_callback_proposal_success(&mut proposal);
+        }
+        self.proposals.insert(&id, &VersionedProposal::V2(proposal));
     }
 
-    /// Receiving callback after the proposal has been finalized.
-    /// If successful, returns bond money to the proposal originator.
-    /// If the proposal execution failed (funds didn't transfer or function call failure),
-    /// move proposal to "Failed" state.
+    /// Receiving callback after the payout of the proposal has finished.
+    /// If the payout failed (funds didn't transfer or function call failure),
+    /// move proposal to "Failed" state, from where the payout can be retried.
     #[private]
     pub fn on_proposal_callback(&mut self, proposal_id: u64) -> PromiseOrValue<()> {
         let mut proposal: Proposal = self
@@ -132,11 +969,18 @@ impl Contract {
         );
         let result = match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Successful(_) => self.internal_callback_proposal_success(&mut proposal),
-            PromiseResult::Failed => self.internal_callback_proposal_fail(&mut proposal),
+            PromiseResult::Successful(_) => {
+                self.payouts.remove(&proposal_id);
+                self.reserved_amount -= self.internal_payout_reserve(&proposal);
+                self.internal_callback_proposal_success(&mut proposal)
+            }
+            PromiseResult::Failed => {
+                self.payouts.insert(&proposal_id, &PayoutStatus::Failed);
+                self.internal_callback_proposal_fail(&mut proposal)
+            }
         };
         self.proposals
-            .insert(&proposal_id, &VersionedProposal::Default(proposal));
+            .insert(&proposal_id, &VersionedProposal::V2(proposal));
         result
     }
 }
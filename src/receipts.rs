@@ -0,0 +1,156 @@
+use near_sdk::json_types::U128;
+
+use crate::consts::{GAS_FOR_RECEIPT, GAS_FOR_RECEIPT_QUEUE, NO_DEPOSIT};
+use crate::*;
+
+/// Rules for issuing NEP-171 receipts for approved rows of a registry.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReceiptPolicy {
+    /// NFT contract minting the receipts. This contract must be allowed to mint and burn on it.
+    pub nft_contract_id: AccountId,
+    /// Account the receipts are minted to.
+    pub receiver_id: AccountId,
+    /// Attached to each `nft_mint` call to cover storage on the NFT contract.
+    pub mint_deposit: U128,
+}
+
+/// Token id of the receipt for given row of a registry.
+pub fn receipt_token_id(unique_identifier: &AccountId, row_id: u64) -> String {
+    format!("{}:{}", unique_identifier, row_id)
+}
+
+/// Mint or burn of a receipt waiting for `process_receipts`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingReceipt {
+    pub nft_contract_id: AccountId,
+    /// `nft_mint` or `nft_burn`.
+    pub method_name: String,
+    /// JSON arguments of the call.
+    pub args: String,
+    pub deposit: U128,
+}
+
+impl Contract {
+    /// Queues receipts for rows added by the change and burns for those of deleted rows.
+    pub(crate) fn internal_issue_receipts(&mut self, change: &AppliedChange) {
+        let registry = match self.internal_find_registry(&change.unique_identifier) {
+            Some(registry) => registry,
+            None => return,
+        };
+        let policy = match &registry.receipt_policy {
+            Some(policy) => policy,
+            None => return,
+        };
+        let receipts: Vec<PendingReceipt> = change
+            .rows
+            .iter()
+            .filter_map(|row| match (&row.before, &row.after) {
+                (None, Some(_)) => Some(mint_receipt(policy, registry, row.unique_identifier)),
+                (Some(_), None) => Some(burn_receipt(
+                    policy,
+                    &registry.unique_identifier,
+                    row.unique_identifier,
+                )),
+                _ => None,
+            })
+            .collect();
+        receipts
+            .iter()
+            .for_each(|receipt| self.internal_queue_receipt(receipt));
+    }
+
+    /// Queues burns of the receipts for all rows of a deleted registry.
+    pub(crate) fn internal_burn_receipts(&mut self, registry: &RegistryData) {
+        if let Some(policy) = &registry.receipt_policy {
+            for row in &registry.row {
+                let receipt =
+                    burn_receipt(policy, &registry.unique_identifier, row.unique_identifier);
+                self.internal_queue_receipt(&receipt);
+            }
+        }
+    }
+
+    fn internal_queue_receipt(&mut self, receipt: &PendingReceipt) {
+        self.pending_receipts
+            .insert(&self.last_pending_receipt_id, receipt);
+        self.last_pending_receipt_id += 1;
+    }
+}
+
+fn mint_receipt(policy: &ReceiptPolicy, registry: &RegistryData, row_id: u64) -> PendingReceipt {
+    let extra = json!({ "registry": registry.unique_identifier, "row_id": row_id });
+    let args = json!({
+        "token_id": receipt_token_id(&registry.unique_identifier, row_id),
+        "receiver_id": policy.receiver_id,
+        "token_metadata": {
+            "title": format!("{} #{}", registry.name, row_id),
+            "description": format!("Row {} of registry {}", row_id, registry.unique_identifier),
+            "issued_at": env::block_timestamp().to_string(),
+            "extra": extra.to_string(),
+        },
+    });
+    PendingReceipt {
+        nft_contract_id: policy.nft_contract_id.clone(),
+        method_name: "nft_mint".to_string(),
+        args: args.to_string(),
+        deposit: policy.mint_deposit,
+    }
+}
+
+fn burn_receipt(
+    policy: &ReceiptPolicy,
+    unique_identifier: &AccountId,
+    row_id: u64,
+) -> PendingReceipt {
+    let args = json!({ "token_id": receipt_token_id(unique_identifier, row_id) });
+    PendingReceipt {
+        nft_contract_id: policy.nft_contract_id.clone(),
+        method_name: "nft_burn".to_string(),
+        args: args.to_string(),
+        deposit: U128(NO_DEPOSIT),
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Mints a receipt for every row approved from now on and burns it once the row is deleted.
+    /// `None` stops issuing receipts. Only for the registry owner.
+    pub fn set_registry_receipt_policy(
+        &mut self,
+        unique_identifier: AccountId,
+        receipt_policy: Option<ReceiptPolicy>,
+    ) {
+        let registry = self.internal_owned_registry_mut(&unique_identifier);
+        registry.receipt_policy = receipt_policy;
+    }
+
+    /// Sends up to `limit` queued mints and burns, oldest first. Anyone can call it.
+    /// Stops early when gas runs low or the balance doesn't cover a mint deposit.
+    /// Returns the number of receipts sent.
+    pub fn process_receipts(&mut self, limit: u64) -> u64 {
+        let mut sent = 0;
+        while sent < limit && self.first_pending_receipt_id < self.last_pending_receipt_id {
+            let id = self.first_pending_receipt_id;
+            let receipt = self.pending_receipts.get(&id).expect("ERR_NO_RECEIPT");
+            if env::prepaid_gas() - env::used_gas() < GAS_FOR_RECEIPT + GAS_FOR_RECEIPT_QUEUE {
+                break;
+            }
+            if receipt.deposit.0 > self.internal_available_balance() {
+                env::log_str("ERR_NOT_ENOUGH_BALANCE");
+                break;
+            }
+            Promise::new(receipt.nft_contract_id).function_call(
+                receipt.method_name,
+                receipt.args.into_bytes(),
+                receipt.deposit.0,
+                GAS_FOR_RECEIPT,
+            );
+            self.pending_receipts.remove(&id);
+            self.first_pending_receipt_id += 1;
+            sent += 1;
+        }
+        sent
+    }
+}
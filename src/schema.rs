@@ -0,0 +1,218 @@
+use std::collections::BTreeSet;
+
+use near_sdk::borsh::maybestd::io::{Error, ErrorKind, Result};
+
+use crate::canonical::canonical_json;
+use crate::consts::MAX_REENCODED_ROWS;
+use crate::*;
+
+/// Marks row data stored in the compact binary encoding. JSON never starts with this byte.
+const BINARY_ROW: u8 = 0;
+
+/// Type of a field of row data.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum FieldKind {
+    String,
+    U64,
+    I64,
+    F64,
+    Bool,
+    /// Any JSON value, stored as canonical JSON.
+    Json,
+}
+
+/// Field of row data, as rows of a registry with a schema are objects.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Field {
+    pub name: String,
+    pub kind: FieldKind,
+}
+
+/// Re-encoding of the rows of a registry after its schema changed.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SchemaMigration {
+    /// Schema the pending rows are still stored by.
+    pub previous_schema: Option<Vec<Field>>,
+    /// Rows not re-encoded yet.
+    pub pending: BTreeSet<u64>,
+}
+
+/// Encodes canonical JSON of a row as a presence byte and Borsh value per schema field,
+/// in schema order. Rows that don't fit the schema are kept as JSON.
+pub(crate) fn encode_row(schema: &[Field], json: Vec<u8>) -> Vec<u8> {
+    match serde_json::from_slice::<Value>(&json)
+        .ok()
+        .and_then(|value| encode_value(schema, &value).ok())
+    {
+        Some(data) => data,
+        None => json,
+    }
+}
+
+fn encode_value(schema: &[Field], value: &Value) -> Result<Vec<u8>> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "ERR_SCHEMA_MISMATCH");
+    let object = value.as_object().ok_or_else(invalid)?;
+    if object
+        .keys()
+        .any(|key| schema.iter().all(|field| field.name != *key))
+    {
+        return Err(invalid());
+    }
+    let mut data = vec![BINARY_ROW];
+    for field in schema {
+        let value = match object.get(&field.name) {
+            Some(value) => value,
+            None => {
+                write(&mut data, &false)?;
+                continue;
+            }
+        };
+        write(&mut data, &true)?;
+        match field.kind {
+            FieldKind::String => write(&mut data, &value.as_str().ok_or_else(invalid)?)?,
+            FieldKind::U64 => write(&mut data, &value.as_u64().ok_or_else(invalid)?)?,
+            FieldKind::I64 => write(&mut data, &value.as_i64().ok_or_else(invalid)?)?,
+            FieldKind::F64 => write(&mut data, &value.as_f64().ok_or_else(invalid)?)?,
+            FieldKind::Bool => write(&mut data, &value.as_bool().ok_or_else(invalid)?)?,
+            FieldKind::Json => write(&mut data, &value.to_string())?,
+        }
+    }
+    Ok(data)
+}
+
+fn write<T: BorshSerialize + ?Sized>(data: &mut Vec<u8>, value: &T) -> Result<()> {
+    BorshSerialize::serialize(value, data)
+}
+
+fn read<T: BorshDeserialize>(data: &mut &[u8]) -> Result<T> {
+    BorshDeserialize::deserialize(data)
+}
+
+/// Canonical JSON of stored row data.
+pub(crate) fn decode_row(schema: Option<&[Field]>, data: &[u8]) -> Vec<u8> {
+    match (data.first(), schema) {
+        (Some(&BINARY_ROW), Some(_)) => canonical_json(&decode_row_value(schema, data)),
+        _ => data.to_vec(),
+    }
+}
+
+/// Value of stored row data, without going through its JSON.
+pub(crate) fn decode_row_value(schema: Option<&[Field]>, data: &[u8]) -> Value {
+    match (data.first(), schema) {
+        (Some(&BINARY_ROW), Some(schema)) => {
+            decode_value(schema, &data[1..]).expect("ERR_CORRUPTED_ROW")
+        }
+        _ => serde_json::from_slice(data).expect("ERR_CORRUPTED_ROW"),
+    }
+}
+
+fn decode_value(schema: &[Field], mut data: &[u8]) -> Result<Value> {
+    let data = &mut data;
+    let mut object = serde_json::Map::new();
+    for field in schema {
+        if !read::<bool>(data)? {
+            continue;
+        }
+        let value = match field.kind {
+            FieldKind::String => Value::from(read::<String>(data)?),
+            FieldKind::U64 => Value::from(read::<u64>(data)?),
+            FieldKind::I64 => Value::from(read::<i64>(data)?),
+            FieldKind::F64 => Value::from(read::<f64>(data)?),
+            FieldKind::Bool => Value::from(read::<bool>(data)?),
+            FieldKind::Json => serde_json::from_str(&read::<String>(data)?)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "ERR_CORRUPTED_ROW"))?,
+        };
+        object.insert(field.name.clone(), value);
+    }
+    Ok(Value::Object(object))
+}
+
+impl RegistryData {
+    /// Schema given row is stored by, which is the previous one until the row is re-encoded.
+    pub(crate) fn row_schema(&self, row: &Row) -> Option<&[Field]> {
+        match &self.schema_migration {
+            Some(migration) if migration.pending.contains(&row.unique_identifier) => {
+                migration.previous_schema.as_deref()
+            }
+            _ => self.schema.as_deref(),
+        }
+    }
+
+    /// Marks given row as stored by the current schema.
+    pub(crate) fn finish_row_migration(&mut self, unique_identifier: u64) {
+        if let Some(migration) = &mut self.schema_migration {
+            migration.pending.remove(&unique_identifier);
+            if migration.pending.is_empty() {
+                self.schema_migration = None;
+            }
+        }
+    }
+
+    /// Re-encodes up to `limit` rows still stored by the previous schema.
+    /// Returns the number of rows left.
+    pub(crate) fn reencode_rows(&mut self, limit: usize) -> usize {
+        let rows: Vec<u64> = match &self.schema_migration {
+            Some(migration) => migration.pending.iter().take(limit).copied().collect(),
+            None => return 0,
+        };
+        for row_id in rows {
+            let json = self.get_row(row_id);
+            self.set_row(row_id, json);
+        }
+        self.schema_migration
+            .as_ref()
+            .map_or(0, |migration| migration.pending.len())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets schema rows of given registry are stored by. Rows fitting the schema are stored
+    /// in a compact binary encoding, others as JSON. Existing rows are re-encoded, up to
+    /// `MAX_REENCODED_ROWS` right away and the rest by `reencode_rows`.
+    /// `None` converts them back to JSON. Only for the registry owner.
+    pub fn set_registry_schema(
+        &mut self,
+        unique_identifier: AccountId,
+        schema: Option<Vec<Field>>,
+    ) {
+        let registry = self.internal_owned_registry_mut(&unique_identifier);
+        assert!(
+            registry.schema_migration.is_none(),
+            "ERR_MIGRATION_IN_PROGRESS"
+        );
+        if let Some(schema) = &schema {
+            assert!(
+                schema
+                    .iter()
+                    .enumerate()
+                    .all(|(i, field)| schema[..i].iter().all(|other| other.name != field.name)),
+                "ERR_DUPLICATE_FIELD"
+            );
+        }
+        if !registry.row.is_empty() {
+            registry.schema_migration = Some(SchemaMigration {
+                previous_schema: registry.schema.take(),
+                pending: registry
+                    .row
+                    .iter()
+                    .map(|row| row.unique_identifier)
+                    .collect(),
+            });
+        }
+        registry.schema = schema;
+        registry.reencode_rows(MAX_REENCODED_ROWS);
+    }
+
+    /// Re-encodes up to `limit` rows of given registry left by a schema change.
+    /// Anyone can call it. Returns the number of rows left.
+    pub fn reencode_rows(&mut self, unique_identifier: AccountId, limit: u64) -> u64 {
+        let registry = self
+            .internal_find_registry_mut(&unique_identifier)
+            .expect("ERR_NO_REGISTRY");
+        registry.reencode_rows(limit as usize) as u64
+    }
+}
@@ -0,0 +1,72 @@
+use near_sdk::json_types::U64;
+
+use crate::types::LocalVote;
+use crate::*;
+
+/// How stake of local votes on proposals to a registry is turned into their weight.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VoteStrategy {
+    /// Weight is the stake.
+    Linear,
+    /// Weight is the square root of the stake.
+    Quadratic,
+    /// Weight grows from zero to the stake over `period` nanoseconds since the vote.
+    Conviction { period: U64 },
+}
+
+impl VoteStrategy {
+    /// Weight of given stake voted at `voted_at`, as of `now`.
+    pub fn weight(&self, stake: Balance, voted_at: u64, now: u64) -> Balance {
+        match self {
+            VoteStrategy::Linear => stake,
+            VoteStrategy::Quadratic => integer_sqrt(stake),
+            VoteStrategy::Conviction { period } => {
+                if period.0 == 0 {
+                    return stake;
+                }
+                let period = period.0 as Balance;
+                let elapsed = (now.saturating_sub(voted_at) as Balance).min(period);
+                stake / period * elapsed + stake % period * elapsed / period
+            }
+        }
+    }
+}
+
+/// Largest integer whose square is not greater than `value`.
+fn integer_sqrt(value: Balance) -> Balance {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = x / 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+impl Contract {
+    /// Weight of given local vote on the proposal, following the strategy of its registry
+    /// at submission.
+    pub(crate) fn internal_vote_weight(&self, proposal: &Proposal, vote: &LocalVote) -> Balance {
+        proposal
+            .vote_strategy
+            .weight(vote.stake, vote.voted_at, env::block_timestamp())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets how local votes on proposals to given registry are weighted.
+    /// Proposals keep the strategy they were submitted with. Only for the registry owner.
+    pub fn set_registry_vote_strategy(
+        &mut self,
+        unique_identifier: AccountId,
+        vote_strategy: VoteStrategy,
+    ) {
+        let registry = self.internal_owned_registry_mut(&unique_identifier);
+        registry.vote_strategy = vote_strategy;
+    }
+}
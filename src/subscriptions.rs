@@ -0,0 +1,260 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::Gas;
+
+use crate::consts::{
+    GAS_FOR_RESOLVE_TRANSFER, MAX_MISSED_NOTIFICATIONS, MAX_SUBSCRIBERS, MAX_SUBSCRIBER_GAS,
+    NO_DEPOSIT, SUBSCRIPTION_DEPOSIT,
+};
+use crate::*;
+
+/// Contract notified with `on_registry_change` after each change applied to a registry.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Subscription {
+    pub account_id: AccountId,
+    /// Gas attached to each notification.
+    pub gas: U64,
+    /// Storage deposit, refunded when the subscription is removed.
+    pub deposit: U128,
+}
+
+/// Notification skipped for lack of gas, sent again by `retry_notifications`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct MissedNotification {
+    pub account_id: AccountId,
+    pub version: u64,
+    pub change_summary: ChangeSummary,
+}
+
+/// What an applied proposal changed in a registry.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct ChangeSummary {
+    pub proposal_id: u64,
+    /// Policy label of the proposal kind, e.g. `add_rows`.
+    pub kind: String,
+    /// Rows added, updated or deleted.
+    pub rows: Vec<u64>,
+    /// Columns added, updated or deleted.
+    pub columns: Vec<u64>,
+}
+
+impl RegistryData {
+    /// Bumps version of the registry and notifies its subscribers of the change.
+    /// Each notification is a separate promise, so a failing subscriber doesn't affect
+    /// the change or other subscribers. Notifications that don't fit in the remaining gas
+    /// are recorded for `retry_notifications`.
+    pub(crate) fn publish_change(&mut self, summary: ChangeSummary) {
+        self.version += 1;
+        for subscription in self.subscribers.clone() {
+            if !notify(
+                &self.unique_identifier,
+                &subscription,
+                self.version,
+                &summary,
+            ) {
+                self.record_missed_notification(MissedNotification {
+                    account_id: subscription.account_id,
+                    version: self.version,
+                    change_summary: summary.clone(),
+                });
+            }
+        }
+    }
+
+    /// Keeps the notification for a retry, dropping the oldest one if too many are kept.
+    fn record_missed_notification(&mut self, notification: MissedNotification) {
+        env::log_str(&format!(
+            "Not enough gas to notify {}",
+            notification.account_id
+        ));
+        if self.missed_notifications.len() >= MAX_MISSED_NOTIFICATIONS {
+            let dropped = self.missed_notifications.remove(0);
+            env::log_str(&format!(
+                "Dropped notification of version {} to {}",
+                dropped.version, dropped.account_id
+            ));
+        }
+        self.missed_notifications.push(notification);
+    }
+
+    /// Refunds deposits of all subscribers, e.g. once the registry is deleted.
+    pub(crate) fn refund_subscribers(&mut self) {
+        for subscription in self.subscribers.drain(..) {
+            if subscription.deposit.0 > 0 {
+                Promise::new(subscription.account_id).transfer(subscription.deposit.0);
+            }
+        }
+    }
+}
+
+/// Calls `on_registry_change` of the subscriber. Returns `false` if there isn't enough gas left.
+fn notify(
+    registry_id: &AccountId,
+    subscription: &Subscription,
+    version: u64,
+    summary: &ChangeSummary,
+) -> bool {
+    let remaining = env::prepaid_gas().0 - env::used_gas().0;
+    if remaining < subscription.gas.0 + GAS_FOR_RESOLVE_TRANSFER.0 {
+        return false;
+    }
+    let args = json!({
+        "registry_id": registry_id,
+        "version": version,
+        "change_summary": summary,
+    })
+    .to_string()
+    .into_bytes();
+    Promise::new(subscription.account_id.clone()).function_call(
+        "on_registry_change".to_string(),
+        args,
+        NO_DEPOSIT,
+        Gas(subscription.gas.0),
+    );
+    true
+}
+
+impl Contract {
+    /// Publishes change made to a registry by the applied proposal.
+    pub(crate) fn internal_publish_change(
+        &mut self,
+        id: u64,
+        proposal: &Proposal,
+        change: Option<&AppliedChange>,
+    ) {
+        if !matches!(
+            proposal.kind,
+            ProposalKind::AddRows
+                | ProposalKind::UpdateRows
+                | ProposalKind::DeleteRows { .. }
+                | ProposalKind::ChangeSchema { .. }
+                | ProposalKind::Revert { .. }
+                | ProposalKind::DisputeRow { .. }
+                | ProposalKind::RenameRegistry { .. }
+                | ProposalKind::TransferRegistryOwnership { .. }
+                | ProposalKind::ChangeRegistryDao { .. }
+        ) {
+            return;
+        }
+        let unique_identifier = change
+            .map(|change| &change.unique_identifier)
+            .unwrap_or(&proposal.unique_identifier);
+        let summary = change_summary(id, proposal, change);
+        if let Some(registry) = self.internal_find_registry_mut(unique_identifier) {
+            registry.publish_change(summary);
+        }
+    }
+}
+
+/// Summary of the change made by given proposal.
+pub(crate) fn change_summary(
+    id: u64,
+    proposal: &Proposal,
+    change: Option<&AppliedChange>,
+) -> ChangeSummary {
+    let ids = |cells: &[CellChange]| cells.iter().map(|cell| cell.unique_identifier).collect();
+    ChangeSummary {
+        proposal_id: id,
+        kind: proposal.kind.to_policy_label().to_string(),
+        rows: change.map(|change| ids(&change.rows)).unwrap_or_default(),
+        columns: change
+            .map(|change| ids(&change.columns))
+            .unwrap_or_default(),
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Subscribes the caller to changes of given registry. It is called with
+    /// `on_registry_change(registry_id, version, change_summary)` and `gas` after each change.
+    /// A new subscription takes `SUBSCRIPTION_DEPOSIT`, subscribing again updates the gas.
+    #[payable]
+    pub fn subscribe(&mut self, unique_identifier: AccountId, gas: U64) {
+        assert!(gas.0 <= MAX_SUBSCRIBER_GAS.0, "ERR_TOO_MUCH_GAS");
+        let account_id = env::predecessor_account_id();
+        let deposit = env::attached_deposit();
+        let registry = self
+            .internal_find_registry_mut(&unique_identifier)
+            .expect("ERR_NO_REGISTRY");
+        match registry
+            .subscribers
+            .iter_mut()
+            .find(|subscription| subscription.account_id == account_id)
+        {
+            Some(subscription) => {
+                assert_eq!(deposit, 0, "ERR_WRONG_DEPOSIT");
+                subscription.gas = gas;
+            }
+            None => {
+                assert_eq!(deposit, SUBSCRIPTION_DEPOSIT, "ERR_WRONG_DEPOSIT");
+                assert!(
+                    registry.subscribers.len() < MAX_SUBSCRIBERS,
+                    "ERR_TOO_MANY_SUBSCRIBERS"
+                );
+                registry.subscribers.push(Subscription {
+                    account_id,
+                    gas,
+                    deposit: U128(deposit),
+                });
+            }
+        }
+    }
+
+    /// Removes subscriber from given registry. For the subscriber itself or the registry owner.
+    pub fn unsubscribe(&mut self, unique_identifier: AccountId, account_id: AccountId) {
+        let registry = self
+            .internal_find_registry_mut(&unique_identifier)
+            .expect("ERR_NO_REGISTRY");
+        let caller = env::predecessor_account_id();
+        assert!(
+            caller == account_id || caller == registry.owner,
+            "ERR_NOT_ALLOWED"
+        );
+        if let Some(index) = registry
+            .subscribers
+            .iter()
+            .position(|subscription| subscription.account_id == account_id)
+        {
+            let subscription = registry.subscribers.remove(index);
+            if subscription.deposit.0 > 0 {
+                Promise::new(account_id.clone()).transfer(subscription.deposit.0);
+            }
+        }
+        registry
+            .missed_notifications
+            .retain(|notification| notification.account_id != account_id);
+    }
+
+    /// Sends again up to `limit` notifications skipped for lack of gas, oldest first.
+    /// Anyone can call it. Returns the number of notifications sent.
+    pub fn retry_notifications(&mut self, unique_identifier: AccountId, limit: u64) -> u64 {
+        let registry = self
+            .internal_find_registry_mut(&unique_identifier)
+            .expect("ERR_NO_REGISTRY");
+        let mut sent = 0;
+        while sent < limit && !registry.missed_notifications.is_empty() {
+            let notification = &registry.missed_notifications[0];
+            if let Some(subscription) = registry
+                .subscribers
+                .iter()
+                .find(|subscription| subscription.account_id == notification.account_id)
+            {
+                if !notify(
+                    &registry.unique_identifier,
+                    subscription,
+                    notification.version,
+                    &notification.change_summary,
+                ) {
+                    break;
+                }
+                sent += 1;
+            }
+            registry.missed_notifications.remove(0);
+        }
+        sent
+    }
+}
@@ -0,0 +1,163 @@
+use near_sdk::json_types::{Base64VecU8, U128};
+
+use crate::consts::{GAS_FOR_TOKEN_GATE_QUERY, NO_DEPOSIT};
+use crate::types::ProposalInput;
+use crate::*;
+
+/// Holdings required to propose or vote on proposals to a registry.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TokenGate {
+    /// At least `min_balance` of given NEP-141 token.
+    FungibleToken {
+        token_id: AccountId,
+        min_balance: U128,
+    },
+    /// Any token of given NEP-171 collection.
+    NonFungibleToken { contract_id: AccountId },
+}
+
+impl Contract {
+    /// Token gate of the registry given proposal targets, if any.
+    pub(crate) fn internal_token_gate(&self, unique_identifier: &AccountId) -> Option<TokenGate> {
+        self.internal_find_registry(unique_identifier)
+            .and_then(|registry| registry.token_gate.clone())
+    }
+
+    /// Queries holdings of given account required by the gate.
+    pub(crate) fn internal_query_token_gate(
+        &self,
+        gate: &TokenGate,
+        account_id: &AccountId,
+    ) -> Promise {
+        let (contract_id, method_name, args) = match gate {
+            TokenGate::FungibleToken { token_id, .. } => (
+                token_id,
+                "ft_balance_of",
+                json!({ "account_id": account_id }),
+            ),
+            TokenGate::NonFungibleToken { contract_id } => (
+                contract_id,
+                "nft_tokens_for_owner",
+                json!({ "account_id": account_id, "limit": 1 }),
+            ),
+        };
+        Promise::new(contract_id.clone()).function_call(
+            method_name.to_string(),
+            args.to_string().into_bytes(),
+            NO_DEPOSIT,
+            GAS_FOR_TOKEN_GATE_QUERY,
+        )
+    }
+
+    /// Checks result of the query made by `internal_query_token_gate`.
+    pub(crate) fn internal_passes_token_gate(&self, gate: &TokenGate) -> bool {
+        assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
+        let value = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => value,
+            PromiseResult::Failed => return false,
+        };
+        match gate {
+            TokenGate::FungibleToken { min_balance, .. } => {
+                matches!(serde_json::from_slice::<U128>(&value), Ok(balance) if balance.0 >= min_balance.0)
+            }
+            TokenGate::NonFungibleToken { .. } => {
+                matches!(serde_json::from_slice::<Vec<Value>>(&value), Ok(tokens) if !tokens.is_empty())
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Restricts proposing and voting on proposals to given registry to holders of a token.
+    /// `None` lets anyone take part. Only for the registry owner.
+    pub fn set_registry_token_gate(
+        &mut self,
+        unique_identifier: AccountId,
+        token_gate: Option<TokenGate>,
+    ) {
+        let registry = self.internal_owned_registry_mut(&unique_identifier);
+        registry.token_gate = token_gate;
+    }
+
+    /// Adds proposal of `proposer` once the token gate of its registry passed.
+    /// Otherwise, or if the proposal can no longer be added, refunds the bond and returns `None`.
+    #[private]
+    pub fn on_token_gate_proposal_callback(
+        &mut self,
+        proposer: AccountId,
+        proposal: ProposalInput,
+        bond: U128,
+    ) -> Option<u64> {
+        let gate = self.internal_token_gate(&proposal.unique_identifier);
+        let checked = match &gate {
+            Some(gate) if !self.internal_passes_token_gate(gate) => Err("ERR_NOT_TOKEN_HOLDER"),
+            _ => self.internal_check_proposal(&proposer, proposal, bond.0),
+        };
+        match checked {
+            Ok(proposal) => Some(self.internal_insert_proposal(proposal, bond.0)),
+            Err(err) => {
+                env::log_str(err);
+                if bond.0 > 0 {
+                    Promise::new(proposer).transfer(bond.0);
+                }
+                None
+            }
+        }
+    }
+
+    /// Records vote of `account_id` once the token gate of the proposal's registry passed.
+    #[private]
+    pub fn on_token_gate_vote_callback(
+        &mut self,
+        id: u64,
+        account_id: AccountId,
+        action: Action,
+        amount: U128,
+    ) {
+        let proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        if let Some(gate) = self.internal_token_gate(&proposal.unique_identifier) {
+            assert!(
+                self.internal_passes_token_gate(&gate),
+                "ERR_NOT_TOKEN_HOLDER"
+            );
+        }
+        self.internal_act_proposal(id, account_id, action, amount);
+    }
+
+    /// Commits vote of `account_id` once the token gate of the proposal's registry passed.
+    /// Otherwise the deposit is refunded. Returns whether the vote was committed.
+    #[private]
+    pub fn on_token_gate_commit_callback(
+        &mut self,
+        id: u64,
+        account_id: AccountId,
+        hash: Base64VecU8,
+        deposit: U128,
+    ) -> bool {
+        let result = self
+            .proposals
+            .get(&id)
+            .map(Proposal::from)
+            .and_then(|proposal| self.internal_token_gate(&proposal.unique_identifier))
+            .map_or(Ok(()), |gate| {
+                if self.internal_passes_token_gate(&gate) {
+                    Ok(())
+                } else {
+                    Err("ERR_NOT_TOKEN_HOLDER")
+                }
+            })
+            .and_then(|_| self.internal_check_commit(id, &account_id, &hash, deposit.0));
+        if let Err(err) = result {
+            env::log_str(err);
+            if deposit.0 > 0 {
+                Promise::new(account_id).transfer(deposit.0);
+            }
+            return false;
+        }
+        self.internal_commit_vote(id, account_id, hash, deposit.0);
+        true
+    }
+}
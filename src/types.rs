@@ -1,4 +1,13 @@
-use crate::{consts::OLD_BASE_TOKEN, Column, Row};
+use crate::{
+    canonical::{canonical_json, content_hash},
+    commit_reveal::CommitRevealPolicy,
+    consts::OLD_BASE_TOKEN,
+    merkle::ProofStep,
+    migration::RowV1,
+    optimistic::OptimisticPolicy,
+    strategies::VoteStrategy,
+    Column, Row,
+};
 use near_sdk::{
     borsh::{self, BorshDeserialize, BorshSerialize},
     env,
@@ -19,6 +28,10 @@ pub enum Action {
     VoteApprove,
     /// Vote to reject given proposal or bounty.
     VoteReject,
+    /// Remove given proposal without returning the bond.
+    RemoveProposal,
+    /// Finalize proposal, called when it's expired to return the funds.
+    Finalize,
 }
 
 impl Action {
@@ -27,6 +40,16 @@ impl Action {
     }
 }
 
+/// State of the payout of an approved proposal that pays out from this contract.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PayoutStatus {
+    /// Payout was sent and its result is not known yet.
+    Pending,
+    /// Payout failed and can be retried with `retry_payout`.
+    Failed,
+}
+
 /// Status of a proposal.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(crate = "near_sdk::serde")]
@@ -41,16 +64,23 @@ pub enum ProposalStatus {
     Removed,
     /// Expired after period of time.
     Expired,
-    /// If proposal was moved to Hub or somewhere else.
+    /// If proposal was moved to Hub or somewhere else, e.g. amended into a new revision.
     Moved,
     /// If proposal has failed when finalizing. Allowed to re-finalize again to either expire or approved.
     Failed,
+    /// Withdrawn by the proposer while in progress.
+    Cancelled,
+    /// Approved, waiting for the timelock of its registry to be applied.
+    Queued,
+    /// Vetoed by the guardian of its registry while queued.
+    Vetoed,
 }
 
 /// Function call arguments.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Clone, Debug))]
 #[serde(crate = "near_sdk::serde")]
+#[allow(dead_code)]
 pub struct ActionCall {
     method_name: String,
     args: Base64VecU8,
@@ -58,10 +88,43 @@ pub struct ActionCall {
     gas: U64,
 }
 
+/// Rules for refunding the bond of a proposal withdrawn by its proposer.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawPolicy {
+    /// Bond is refunded in full if withdrawn within this period after submission, before any vote.
+    pub grace_period: U64,
+    /// Share of the bond refunded otherwise, in basis points.
+    pub refund_bps: u32,
+}
+
+impl WithdrawPolicy {
+    /// Part of `bond` refunded for a proposal submitted at `submission_time`.
+    pub fn refund_amount(&self, bond: Balance, submission_time: U64, has_votes: bool) -> Balance {
+        if !has_votes && env::block_timestamp() < submission_time.0 + self.grace_period.0 {
+            bond
+        } else {
+            bond * self.refund_bps as u128 / 10_000
+        }
+    }
+}
+
+/// Rules for amending a proposal into a new revision.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct AmendPolicy {
+    /// If set, local votes are carried over to the new revision, otherwise voting starts over.
+    /// Votes in the DAO always start over.
+    pub preserve_votes: bool,
+}
+
 /// Function call arguments.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Clone, Debug))]
 #[serde(crate = "near_sdk::serde")]
+#[allow(dead_code)]
 pub struct PolicyParameters {
     pub proposal_bond: Option<U128>,
     pub proposal_period: Option<U64>,
@@ -74,7 +137,7 @@ pub struct PolicyParameters {
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
 #[serde(crate = "near_sdk::serde")]
 pub enum ProposalKind {
-    /// Transfers given amount of `token_id` from this DAO to `receiver_id`.
+    /// Transfers given amount of `token_id` from this contract to `receiver_id`.
     /// If `msg` is not None, calls `ft_transfer_call` with given `msg`. Fails if this base token.
     /// For `ft_transfer` and `ft_transfer_call` `memo` is the `description` of the proposal.
     Transfer {
@@ -88,6 +151,30 @@ pub enum ProposalKind {
     SetStakingContract { staking_id: AccountId },
     /// Just a signaling vote, with no execution.
     Vote,
+    /// Restores rows and columns changed by given applied proposal to their prior values.
+    Revert { proposal_id: u64 },
+    /// Adds rows of the proposal to the registry.
+    AddRows,
+    /// Replaces data of existing rows with rows of the proposal.
+    UpdateRows,
+    /// Deletes given rows of the registry.
+    DeleteRows { row_ids: Vec<u64> },
+    /// Adds or replaces columns of the proposal and deletes `remove_columns`.
+    ChangeSchema { remove_columns: Vec<u64> },
+    /// Renames the registry. Its unique identifier stays the same.
+    RenameRegistry { name: String },
+    /// Makes `new_owner` the owner of the registry, moving it to their registries.
+    TransferRegistryOwnership { new_owner: AccountId },
+    /// Changes the DAO the registry belongs to.
+    ChangeRegistryDao { dao: AccountId },
+    /// Deletes the registry with all its rows and columns.
+    DeleteRegistry,
+    /// Claims given bounty by adding rows of the proposal to its registry.
+    /// The proposer is paid the bounty on approval.
+    BountyDone { bounty_id: u64 },
+    /// Disputes given row, staking `bond`. Submitted with `challenge_row`.
+    /// The row is removed on approval and the challenger rewarded.
+    DisputeRow { row_id: u64, bond: U128 },
 }
 
 impl ProposalKind {
@@ -97,6 +184,27 @@ impl ProposalKind {
             ProposalKind::Transfer { .. } => "transfer",
             ProposalKind::SetStakingContract { .. } => "set_vote_token",
             ProposalKind::Vote => "vote",
+            ProposalKind::Revert { .. } => "revert",
+            ProposalKind::AddRows => "add_rows",
+            ProposalKind::UpdateRows => "update_rows",
+            ProposalKind::DeleteRows { .. } => "delete_rows",
+            ProposalKind::ChangeSchema { .. } => "change_schema",
+            ProposalKind::RenameRegistry { .. } => "rename_registry",
+            ProposalKind::TransferRegistryOwnership { .. } => "transfer_registry_ownership",
+            ProposalKind::ChangeRegistryDao { .. } => "change_registry_dao",
+            ProposalKind::DeleteRegistry => "delete_registry",
+            ProposalKind::BountyDone { .. } => "bounty_done",
+            ProposalKind::DisputeRow { .. } => "dispute_row",
+        }
+    }
+
+    /// Kind of the proposal mirrored in the DAO.
+    /// Registry kinds are unknown to the DAO, so they are voted on as a signaling `Vote`.
+    /// So are the kinds paid out from this contract rather than by the DAO, like `Transfer`.
+    pub fn to_dao_kind(&self) -> ProposalKind {
+        match self {
+            ProposalKind::SetStakingContract { .. } => self.clone(),
+            _ => ProposalKind::Vote,
         }
     }
 }
@@ -158,6 +266,37 @@ pub struct Proposal {
     /// New row
     pub(crate) new_rows: Vec<Row>,
     pub unique_identifier: AccountId,
+    /// Bond still locked for this proposal. Zeroed once it is refunded or forfeited.
+    pub bond: U128,
+    /// Id of the mirrored proposal in the DAO, known once the DAO accepted it.
+    pub dao_proposal_id: Option<u64>,
+    /// Vote this contract cast in the DAO on behalf of local voters, once relayed.
+    pub relayed_vote: Option<Action>,
+    /// Total delegated voting power when the proposal was submitted.
+    pub total_voting_power: U128,
+    /// Proposal this one is a revision of.
+    pub amends: Option<u64>,
+    /// Revision that replaced this proposal.
+    pub amended_by: Option<u64>,
+    /// When a queued proposal can be applied.
+    pub executable_at: Option<U64>,
+    /// Content hashes the rows must still have when the proposal is added and applied.
+    pub expected_hashes: HashMap<u64, Base64VecU8>,
+    /// Optimistic policy of the registry at submission. Such proposals are decided here
+    /// and not mirrored in the DAO.
+    pub optimistic_policy: Option<OptimisticPolicy>,
+    /// Commit-reveal policy of the registry at submission. Such proposals are decided here
+    /// and not mirrored in the DAO.
+    pub commit_reveal_policy: Option<CommitRevealPolicy>,
+    /// Vote strategy of the registry at submission, used to weight local votes.
+    pub vote_strategy: VoteStrategy,
+}
+
+impl Proposal {
+    /// Whether the proposal is mirrored in the DAO and decided by it.
+    pub fn is_mirrored(&self) -> bool {
+        self.optimistic_policy.is_none() && self.commit_reveal_policy.is_none()
+    }
 }
 
 /// Proposal that are sent to this DAO.
@@ -174,7 +313,7 @@ pub struct NewProposal {
     /// Current status of the proposal.
     pub status: ProposalStatus,
     /// Count of votes per role per decision: yes / no / spam.
-    pub vote_counts: HashMap<String, [Balance; 3]>,
+    pub vote_counts: HashMap<String, Vec<U128>>,
     /// Map of who voted and how.
     pub votes: HashMap<AccountId, Vote>,
     /// Submission time (for voting period).
@@ -183,48 +322,126 @@ pub struct NewProposal {
     pub row: Vec<Row>,
     pub column: Vec<Column>,
     pub unique_identifier: AccountId,
+    /// Bond still locked for this proposal.
+    pub bond: U128,
+    /// Id of the mirrored proposal in the DAO.
+    pub dao_proposal_id: Option<u64>,
+    /// Vote this contract cast in the DAO on behalf of local voters.
+    pub relayed_vote: Option<Action>,
+    /// Total delegated voting power when the proposal was submitted.
+    pub total_voting_power: U128,
+    /// Proposal this one is a revision of.
+    pub amends: Option<u64>,
+    /// Revision that replaced this proposal.
+    pub amended_by: Option<u64>,
+    /// When a queued proposal can be applied.
+    pub executable_at: Option<U64>,
+    /// Content hashes the rows must still have when the proposal is applied.
+    pub expected_hashes: HashMap<u64, Base64VecU8>,
+    /// Optimistic policy of the registry at submission.
+    pub optimistic_policy: Option<OptimisticPolicy>,
+    /// Commit-reveal policy of the registry at submission.
+    pub commit_reveal_policy: Option<CommitRevealPolicy>,
+    /// Vote strategy of the registry at submission.
+    pub vote_strategy: VoteStrategy,
+}
+
+/// Proposal as stored by the first version of the contract.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProposalV1 {
+    pub proposer: AccountId,
+    pub description: String,
+    pub kind: ProposalKind,
+    pub status: ProposalStatus,
+    pub vote_counts: HashMap<String, [Balance; 3]>,
+    pub votes: HashMap<AccountId, Vote>,
+    pub submission_time: U64,
+    pub new_registries: HashMap<AccountId, Vec<(AccountId, Vec<u8>)>>,
+    pub(crate) new_columns: Vec<Column>,
+    pub(crate) new_rows: Vec<RowV1>,
+    pub unique_identifier: AccountId,
 }
 
+impl From<ProposalV1> for Proposal {
+    fn from(p: ProposalV1) -> Self {
+        Self {
+            proposer: p.proposer,
+            description: p.description,
+            kind: p.kind,
+            status: p.status,
+            vote_counts: p.vote_counts,
+            votes: p.votes,
+            submission_time: p.submission_time,
+            new_registries: p.new_registries,
+            new_columns: p.new_columns,
+            new_rows: p.new_rows.into_iter().map(Row::from).collect(),
+            unique_identifier: p.unique_identifier,
+            bond: U128(0),
+            dao_proposal_id: None,
+            relayed_vote: None,
+            total_voting_power: U128(0),
+            amends: None,
+            amended_by: None,
+            executable_at: None,
+            expected_hashes: HashMap::new(),
+            optimistic_policy: None,
+            commit_reveal_policy: None,
+            vote_strategy: VoteStrategy::Linear,
+        }
+    }
+}
+
+/// Proposal in any layout it was stored in. `Default` is the layout of the first version,
+/// upgraded to the current one when read.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
 #[serde(crate = "near_sdk::serde")]
+#[allow(clippy::large_enum_variant)]
 pub enum VersionedProposal {
-    Default(Proposal),
+    Default(ProposalV1),
+    V2(Proposal),
 }
 
 impl From<VersionedProposal> for Proposal {
     fn from(v: VersionedProposal) -> Self {
         match v {
-            VersionedProposal::Default(p) => p,
+            VersionedProposal::Default(p) => p.into(),
+            VersionedProposal::V2(p) => p,
         }
     }
 }
 
 impl From<VersionedProposal> for NewProposal {
     fn from(v: VersionedProposal) -> Self {
-        match v {
-            VersionedProposal::Default(p) => {
-                let mut new_column = Vec::new();
-                let mut new_row = Vec::new();
-                for column in p.new_columns {
-                    new_column.push(column);
-                }
-                for row in p.new_rows {
-                    new_row.push(row);
-                }
-                Self {
-                    proposer: p.proposer,
-                    description: p.description,
-                    kind: p.kind,
-                    status: p.status,
-                    vote_counts: p.vote_counts,
-                    votes: p.votes,
-                    submission_time: p.submission_time,
-                    row: new_row,
-                    column: new_column,
-                    unique_identifier: p.unique_identifier,
-                }
-            }
+        let p: Proposal = v.into();
+        Self {
+            proposer: p.proposer,
+            description: p.description,
+            kind: p.kind,
+            status: p.status,
+            vote_counts: p
+                .vote_counts
+                .into_iter()
+                .map(|(role, counts)| (role, counts.map(U128).to_vec()))
+                .collect(),
+            votes: p.votes,
+            submission_time: p.submission_time,
+            row: p.new_rows,
+            column: p.new_columns,
+            unique_identifier: p.unique_identifier,
+            bond: p.bond,
+            dao_proposal_id: p.dao_proposal_id,
+            relayed_vote: p.relayed_vote,
+            total_voting_power: p.total_voting_power,
+            amends: p.amends,
+            amended_by: p.amended_by,
+            executable_at: p.executable_at,
+            expected_hashes: p.expected_hashes,
+            optimistic_policy: p.optimistic_policy,
+            commit_reveal_policy: p.commit_reveal_policy,
+            vote_strategy: p.vote_strategy,
         }
     }
 }
@@ -242,6 +459,10 @@ pub struct ProposalInput {
     pub row: Vec<Value>,
     /// UUID
     pub unique_identifier: AccountId,
+    /// Content hashes the rows must still have for the proposal to be applied,
+    /// so it doesn't overwrite changes made since it was written.
+    #[serde(default)]
+    pub expected_hashes: HashMap<u64, Base64VecU8>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -257,17 +478,19 @@ impl From<ProposalInput> for Proposal {
     fn from(input: ProposalInput) -> Self {
         let mut rows = Vec::new();
         for row in input.row {
-            let row_data: Vec<u8> = row["data"].clone().to_string().as_bytes().to_vec();
+            let row_data = canonical_json(&row["data"]);
             let row_identifier: u64 =
                 serde_json::from_value(row["unique_identifier"].clone()).unwrap();
             rows.push(Row {
                 unique_identifier: row_identifier,
+                content_hash: content_hash(&row_data),
                 data: row_data,
+                attribution: None,
             });
         }
         let mut columns = Vec::new();
         for column in input.column {
-            let column_data: Vec<u8> = column["data"].clone().to_string().as_bytes().to_vec();
+            let column_data = canonical_json(&column["data"]);
             let column_identifier: u64 =
                 serde_json::from_value(column["unique_identifier"].clone()).unwrap();
             columns.push(Column {
@@ -287,6 +510,99 @@ impl From<ProposalInput> for Proposal {
             new_columns: columns,
             new_registries: Default::default(),
             unique_identifier: input.unique_identifier,
+            bond: U128(0),
+            dao_proposal_id: None,
+            relayed_vote: None,
+            total_voting_power: U128(0),
+            amends: None,
+            amended_by: None,
+            executable_at: None,
+            expected_hashes: input.expected_hashes,
+            optimistic_policy: None,
+            commit_reveal_policy: None,
+            vote_strategy: VoteStrategy::Linear,
+        }
+    }
+}
+
+/// Value of a row or column before and after a proposal was applied.
+/// `None` if it didn't exist.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct CellChange {
+    pub unique_identifier: u64,
+    pub before: Option<Vec<u8>>,
+    pub after: Option<Vec<u8>>,
+}
+
+impl CellChange {
+    /// Change undoing this one.
+    pub fn inverse(&self) -> Self {
+        Self {
+            unique_identifier: self.unique_identifier,
+            before: self.after.clone(),
+            after: self.before.clone(),
+        }
+    }
+}
+
+/// Changes an applied proposal made to its registry, kept to be able to revert them.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct AppliedChange {
+    /// Registry the changes were applied to.
+    pub unique_identifier: AccountId,
+    pub rows: Vec<CellChange>,
+    pub columns: Vec<CellChange>,
+    /// Proposal that reverted these changes.
+    pub reverted_by: Option<u64>,
+}
+
+/// Vote recorded locally, weighted by the vote strategy of the registry when counted.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct LocalVote {
+    pub account_id: AccountId,
+    /// Voting power put behind the vote.
+    pub stake: Balance,
+    pub action: Action,
+    pub voted_at: u64,
+}
+
+/// Compact record kept for a finished proposal once its data is cleaned up.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct ArchivedProposal {
+    /// Original proposer.
+    pub proposer: AccountId,
+    /// Description of this proposal.
+    pub description: String,
+    /// Final status of the proposal.
+    pub status: ProposalStatus,
+    /// Submission time (for voting period).
+    pub submission_time: U64,
+    /// Id of the mirrored proposal in the DAO.
+    pub dao_proposal_id: Option<u64>,
+    /// Registry the proposal targeted.
+    pub unique_identifier: AccountId,
+    /// Proposal this one is a revision of.
+    pub amends: Option<u64>,
+    /// Revision that replaced this proposal.
+    pub amended_by: Option<u64>,
+}
+
+impl From<Proposal> for ArchivedProposal {
+    fn from(proposal: Proposal) -> Self {
+        Self {
+            proposer: proposal.proposer,
+            description: proposal.description,
+            status: proposal.status,
+            submission_time: proposal.submission_time,
+            dao_proposal_id: proposal.dao_proposal_id,
+            unique_identifier: proposal.unique_identifier,
+            amends: proposal.amends,
+            amended_by: proposal.amended_by,
         }
     }
 }
@@ -297,6 +613,10 @@ impl From<ProposalInput> for Proposal {
 pub struct ProposalOutputRegistry {
     /// Id of the proposal.
     pub id: u64,
+    /// End of the voting period, if the DAO policy is cached.
+    pub voting_deadline: Option<U64>,
+    /// Ids of all revisions of the proposal, from the original to the latest.
+    pub revisions: Vec<u64>,
     #[serde(flatten)]
     pub proposal: NewProposal,
 }
@@ -322,6 +642,52 @@ pub struct ProposalOutput {
     /// Submission time (for voting period).
     pub submission_time: U64,
 }
+/// Voting power cast on a proposal, weighted by delegations at submission time.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct VoteTally {
+    /// Voting power of approving votes.
+    pub approve: U128,
+    /// Voting power of rejecting votes.
+    pub reject: U128,
+    /// Total delegated voting power when the proposal was submitted.
+    pub total: U128,
+}
+
+/// Row of a registry with its data decoded.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct RowOutput {
+    pub unique_identifier: u64,
+    pub data: Value,
+}
+
+/// Proof that a row is included in the Merkle root of its registry.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct MerkleProofOutput {
+    pub root: Base64VecU8,
+    pub row_id: u64,
+    /// Row data as stored, i.e. the exact bytes hashed into the leaf.
+    pub data: String,
+    pub proof: Vec<ProofStep>,
+}
+
+/// Vote of a single account on a proposal.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct VoterOutput {
+    pub account_id: AccountId,
+    /// `InProgress` until the DAO confirmed a vote relayed by this contract.
+    pub vote: Vote,
+    /// Local voting power of the vote. Zero for votes cast directly in the DAO.
+    pub weight: U128,
+}
+
 /// In near-sdk v3, the token was represented by a String, with no other restrictions.
 /// That being said, Sputnik used "" (empty String) as a convention to represent the $NEAR token.
 /// In near-sdk v4, the token representation was replaced by AccountId (which is in fact a wrapper
@@ -329,7 +695,6 @@ pub struct ProposalOutput {
 /// Sputnik had to adapt since "" was not allowed anymore and we chose to represent the token as a
 /// Option<AccountId> with the convention that None represents the $NEAR token.
 /// This function is required to help with the transition and keep the backward compatibility.
-#[allow(dead_code)]
 pub fn convert_old_to_new_token(old_account_id: &AccountId) -> Option<AccountId> {
     if *old_account_id == AccountId::from_str(OLD_BASE_TOKEN).unwrap() {
         return None;
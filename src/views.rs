@@ -1,7 +1,10 @@
-use near_sdk::Promise;
+use near_sdk::{json_types::Base64VecU8, Promise};
 
-use crate::consts::{GAS_FOR_RESOLVE_TRANSFER, NO_DEPOSIT};
-use crate::types::{NewProposal, ProposalOutputRegistry};
+use crate::consts::{GAS_FOR_PROPOSAL_RESULT, GAS_FOR_RESOLVE_TRANSFER, NO_DEPOSIT};
+use crate::types::{
+    ArchivedProposal, MerkleProofOutput, NewProposal, PayoutStatus, ProposalOutputRegistry,
+    RowOutput, VoteTally, VoterOutput,
+};
 use crate::*;
 
 #[near_bindgen]
@@ -63,9 +66,12 @@ impl Contract {
     pub fn get_proposal(&self, id: u64) -> Vec<Value> {
         let proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL");
         let mut vec = Vec::new();
+        let proposal: NewProposal = proposal.into();
         let new_proposal: ProposalOutputRegistry = ProposalOutputRegistry {
             id,
-            proposal: proposal.into(),
+            revisions: self.get_revisions(id),
+            voting_deadline: self.internal_voting_deadline(proposal.submission_time),
+            proposal,
         };
         vec.push(serde_json::to_value(new_proposal.clone()).unwrap());
         for row in &new_proposal.proposal.row {
@@ -79,27 +85,262 @@ impl Contract {
         vec
     }
 
+    /// Ids of all revisions of given proposal, from the original to the latest.
+    pub fn get_revisions(&self, id: u64) -> Vec<u64> {
+        // Links of a revision, whether it is still live or archived.
+        let links = |id: u64| -> (Option<u64>, Option<u64>) {
+            match self.proposals.get(&id) {
+                Some(proposal) => {
+                    let proposal: Proposal = proposal.into();
+                    (proposal.amends, proposal.amended_by)
+                }
+                None => self
+                    .archived_proposals
+                    .get(&id)
+                    .map(|archived| (archived.amends, archived.amended_by))
+                    .unwrap_or_default(),
+            }
+        };
+        let mut first = id;
+        while let Some(previous) = links(first).0 {
+            first = previous;
+        }
+        let mut revisions = vec![first];
+        while let Some(next) = links(*revisions.last().unwrap()).1 {
+            revisions.push(next);
+        }
+        revisions
+    }
+
+    /// Contributions of given account: accepted and rejected proposals and rows authored.
+    pub fn get_contributor(&self, account_id: AccountId) -> ContributorStats {
+        self.internal_get_contributor(&account_id)
+    }
+
+    /// Who proposed and approved the current data of given row of the registry, and when.
+    pub fn get_row_attribution(
+        &self,
+        unique_identifier: AccountId,
+        row_id: u64,
+    ) -> Option<RowAttribution> {
+        self.internal_find_registry(&unique_identifier)
+            .and_then(|registry| registry.get_row_attribution(row_id))
+    }
+
+    /// Checks if `account_id` keys a row of given registry.
+    pub fn is_member(&self, unique_identifier: AccountId, account_id: AccountId) -> bool {
+        self.get_row_by_key(unique_identifier, account_id.to_string())
+            .is_some()
+    }
+
+    /// Row of given registry with given key.
+    pub fn get_row_by_key(&self, unique_identifier: AccountId, key: String) -> Option<RowOutput> {
+        self.internal_find_registry(&unique_identifier)
+            .and_then(|registry| registry.find_row_by_key(&key))
+    }
+
+    /// Field of given row of the registry. `None` if the row or field don't exist.
+    pub fn get_field(
+        &self,
+        unique_identifier: AccountId,
+        row_id: u64,
+        field: String,
+    ) -> Option<Value> {
+        self.internal_find_registry(&unique_identifier)
+            .and_then(|registry| registry.get_row_value(row_id))
+            .and_then(|data| data.get(&field).cloned())
+    }
+
+    /// sha256 of the canonical JSON of given row's data.
+    pub fn get_row_hash(&self, unique_identifier: AccountId, row_id: u64) -> Option<Base64VecU8> {
+        self.internal_find_registry(&unique_identifier)
+            .and_then(|registry| registry.get_row_hash(row_id).cloned())
+    }
+
+    /// Merkle root over rows of given registry. `None` if it has no rows.
+    pub fn get_merkle_root(&self, unique_identifier: AccountId) -> Option<Base64VecU8> {
+        self.internal_find_registry(&unique_identifier)
+            .and_then(|registry| registry.merkle.root())
+            .map(|root| Base64VecU8(root.to_vec()))
+    }
+
+    /// Proof that given row is included in the Merkle root of its registry.
+    pub fn get_merkle_proof(
+        &self,
+        unique_identifier: AccountId,
+        row_id: u64,
+    ) -> Option<MerkleProofOutput> {
+        let registry = self.internal_find_registry(&unique_identifier)?;
+        Some(MerkleProofOutput {
+            root: Base64VecU8(registry.merkle.root()?.to_vec()),
+            row_id,
+            data: String::from_utf8_lossy(&registry.get_row(row_id)?).to_string(),
+            proof: registry.merkle.proof(row_id)?,
+        })
+    }
+
+    /// Last bounty's id.
+    pub fn get_last_bounty_id(&self) -> u64 {
+        self.last_bounty_id
+    }
+
+    pub fn get_bounty(&self, id: u64) -> Option<Bounty> {
+        self.bounties.get(&id)
+    }
+
+    /// Compact record of a proposal cleaned up by `cleanup_proposals`.
+    pub fn get_archived_proposal(&self, id: u64) -> Option<ArchivedProposal> {
+        self.archived_proposals.get(&id)
+    }
+
+    /// Receipt mints and burns waiting for `process_receipts`, oldest first.
+    pub fn get_pending_receipts(&self, limit: u64) -> Vec<PendingReceipt> {
+        (self.first_pending_receipt_id..self.last_pending_receipt_id)
+            .take(limit as usize)
+            .filter_map(|id| self.pending_receipts.get(&id))
+            .collect()
+    }
+
+    /// Notifications of given registry skipped for lack of gas, oldest first.
+    pub fn get_missed_notifications(
+        &self,
+        unique_identifier: AccountId,
+    ) -> Vec<MissedNotification> {
+        self.internal_find_registry(&unique_identifier)
+            .map(|registry| registry.missed_notifications.clone())
+            .unwrap_or_default()
+    }
+
+    /// State of the payout of an approved proposal, if in flight or failed.
+    pub fn get_payout(&self, proposal_id: u64) -> Option<PayoutStatus> {
+        self.payouts.get(&proposal_id)
+    }
+
+    /// Rows and columns changed by an applied proposal, with their values before and after.
+    pub fn get_applied_change(&self, proposal_id: u64) -> Option<Value> {
+        let to_json = |changes: &Vec<CellChange>| -> Vec<Value> {
+            changes
+                .iter()
+                .map(|change| {
+                    json!({
+                        "unique_identifier": change.unique_identifier,
+                        "before": change.before.as_ref().map(|data| String::from_utf8_lossy(data)),
+                        "after": change.after.as_ref().map(|data| String::from_utf8_lossy(data)),
+                    })
+                })
+                .collect()
+        };
+        self.applied_changes.get(&proposal_id).map(|change| {
+            json!({
+                "unique_identifier": change.unique_identifier,
+                "rows": to_json(&change.rows),
+                "columns": to_json(&change.columns),
+                "reverted_by": change.reverted_by,
+            })
+        })
+    }
+
     pub fn get_voting_result(&self, proposal_id: u64) -> Vec<Value> {
+        let proposal: Proposal = self
+            .proposals
+            .get(&proposal_id)
+            .expect("ERR_NO_PROPOSAL")
+            .into();
+        let dao_proposal_id = proposal.dao_proposal_id.expect("ERR_PROPOSAL_NOT_IN_DAO");
         let _ = Promise::new(self.dao.clone())
             .function_call(
                 "get_proposal".to_string(),
-                json!({ "id": proposal_id }).to_string().as_bytes().to_vec(),
+                json!({ "id": dao_proposal_id })
+                    .to_string()
+                    .as_bytes()
+                    .to_vec(),
                 NO_DEPOSIT,
                 GAS_FOR_RESOLVE_TRANSFER,
             )
             .then(ext_self::proposal_result_callback(
+                proposal_id,
                 env::current_account_id(),
                 NO_DEPOSIT,
-                GAS_FOR_RESOLVE_TRANSFER,
+                GAS_FOR_PROPOSAL_RESULT,
             ));
         self.get_proposal(proposal_id)
     }
 
+    /// Cached copy of the DAO policy, if fetched.
+    pub fn get_policy(&self) -> Option<DaoPolicy> {
+        self.policy.get()
+    }
+
+    /// Total amount of $NEAR locked for proposal bonds.
+    pub fn get_locked_amount(&self) -> U128 {
+        U128(self.locked_amount)
+    }
+
+    /// Amount of $NEAR locked for bonds of given proposer.
+    pub fn get_locked_bond(&self, account_id: AccountId) -> U128 {
+        U128(self.locked_bonds.get(&account_id).unwrap_or_default())
+    }
+
+    /// Staking tokens deposited by given account and their delegations.
+    pub fn get_stake(&self, account_id: AccountId) -> Stake {
+        self.internal_get_stake(&account_id)
+    }
+
+    /// Voting power delegated to given account.
+    pub fn get_delegation(&self, account_id: AccountId) -> U128 {
+        U128(self.delegations.get(&account_id).unwrap_or_default())
+    }
+
+    /// Total voting power delegated.
+    pub fn get_total_delegation_amount(&self) -> U128 {
+        U128(self.total_delegation_amount)
+    }
+
+    /// Weighted tally of votes recorded locally for given proposal.
+    pub fn get_proposal_tally(&self, id: u64) -> VoteTally {
+        let proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        let mut tally = VoteTally {
+            approve: U128(0),
+            reject: U128(0),
+            total: proposal.total_voting_power,
+        };
+        for vote in self.voting_power.get(&id).into_iter().flatten() {
+            let weight = self.internal_vote_weight(&proposal, vote);
+            match vote.action {
+                Action::VoteApprove => tally.approve.0 += weight,
+                Action::VoteReject => tally.reject.0 += weight,
+                Action::AddProposal | Action::RemoveProposal | Action::Finalize => {}
+            }
+        }
+        tally
+    }
+
+    /// Accounts that voted on given proposal and how.
+    pub fn get_proposal_voters(&self, id: u64) -> Vec<VoterOutput> {
+        let proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
+        let local_votes = self.voting_power.get(&id).cloned().unwrap_or_default();
+        proposal
+            .votes
+            .iter()
+            .map(|(account_id, vote)| VoterOutput {
+                weight: U128(
+                    local_votes
+                        .iter()
+                        .find(|local_vote| local_vote.account_id == *account_id)
+                        .map(|local_vote| self.internal_vote_weight(&proposal, local_vote))
+                        .unwrap_or_default(),
+                ),
+                account_id: account_id.clone(),
+                vote: vote.clone(),
+            })
+            .collect()
+    }
+
     pub fn get_registries(&self) -> Vec<Value> {
         let mut vec = Vec::new();
         for (_account, registry) in self.registries.iter() {
             for data in registry {
-                let registry_data = serde_json::to_value(&data).unwrap();
+                let registry_data = serde_json::to_value(data).unwrap();
                 let mut column_data = Vec::new();
                 for column in &data.column {
                     let new_data: Value = serde_json::from_slice(&column.data).unwrap();
@@ -110,7 +351,7 @@ impl Contract {
                 }
                 let mut row_data = Vec::new();
                 for row in &data.row {
-                    let new_data: Value = serde_json::from_slice(&row.data).unwrap();
+                    let new_data = data.row_value(row);
                     let row_identifier: Value =
                         serde_json::to_value(row.unique_identifier).unwrap();
                     row_data.push(row_identifier);
@@ -145,7 +386,7 @@ impl Contract {
         for (_account, registry) in self.registries.iter() {
             for data in registry {
                 if data.name == name {
-                    let registry_data = serde_json::to_value(&data).unwrap();
+                    let registry_data = serde_json::to_value(data).unwrap();
                     let mut column_data = Vec::new();
                     for column in &data.column {
                         let new_data: Value = serde_json::from_slice(&column.data).unwrap();
@@ -156,7 +397,7 @@ impl Contract {
                     }
                     let mut row_data = Vec::new();
                     for row in &data.row {
-                        let new_data: Value = serde_json::from_slice(&row.data).unwrap();
+                        let new_data = data.row_value(row);
                         let row_identifier: Value =
                             serde_json::to_value(row.unique_identifier).unwrap();
                         row_data.push(row_identifier);
@@ -198,7 +439,7 @@ impl Contract {
                 for row in &proposal.new_rows {
                     let _ = data.row.iter().map(|x| {
                         if row.unique_identifier == x.unique_identifier {
-                            let new_value: Value = serde_json::to_value(&x.clone()).unwrap();
+                            let new_value: Value = serde_json::to_value(x.clone()).unwrap();
                             value = new_value;
                         }
                     });
@@ -206,7 +447,7 @@ impl Contract {
                 for column in &proposal.new_columns {
                     let _ = data.column.iter().map(|x| {
                         if column.unique_identifier == x.unique_identifier {
-                            let new_value: Value = serde_json::to_value(&x.clone()).unwrap();
+                            let new_value: Value = serde_json::to_value(x.clone()).unwrap();
                             value_1 = new_value;
                         }
                     });